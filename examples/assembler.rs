@@ -0,0 +1,43 @@
+use emulator_6502::asm::assemble;
+use emulator_6502::cpu::{run_program, RunLimit};
+use emulator_6502::disasm::{disassemble_range, Symbols};
+
+const SOURCE: &str = "
+; sums up the numbers from 10 down to 1 into $20
+        LDX #10
+        LDA #0
+loop:   JSR add_x
+        DEX
+        BNE loop
+        STA $20
+done:   JMP done        ; traps, which stops the run
+
+add_x:  STX $21
+        CLC
+        ADC $21
+        RTS
+";
+
+fn main() {
+    let program = assemble(SOURCE).expect("the program should assemble");
+    let cpu = run_program(&program.bytes, program.start, RunLimit::Forever);
+
+    let symbols = program
+        .labels
+        .iter()
+        .map(|(label, &address)| (address, label.clone()))
+        .collect::<Symbols>();
+    let end = program.start + program.bytes.len() as u16 - 1;
+    for (address, text) in disassemble_range(&cpu.memory, program.start..=end, Some(&symbols), &[])
+    {
+        let label = symbols.get(&address).map(|label| format!("{}:", label));
+        println!("{:04X}  {:8}{}", address, label.unwrap_or_default(), text);
+    }
+
+    println!();
+    println!(
+        "PC={:04X} A={:02X} X={:02X} Y={:02X} SP={:02X} {}",
+        cpu.pc, cpu.a, cpu.x, cpu.y, cpu.sp, cpu.status
+    );
+    println!("$20={} after {} cycles", cpu.memory[0x20], cpu.cycles);
+}
@@ -0,0 +1,186 @@
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+use crate::cpu::Word;
+use crate::mem::Memory;
+use crate::opcode::{decode_at, AddressingMode, DecodedInstruction, Opcode};
+
+/// A sequence of instructions that is only ever entered at its start and left
+/// after its last instruction.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: Word,
+    pub instructions: Vec<DecodedInstruction>,
+    /// The addresses that execution can continue at after the block, as far as
+    /// they are statically known: the fall-through address and the target of a
+    /// branch or jump. Targets outside the analyzed range are included.
+    pub successors: Vec<Word>,
+}
+
+/// Disassembles `start..=end` and splits it into basic blocks.
+///
+/// Blocks start at `start`, at the targets of branches, JMP and JSR and after
+/// instructions that end a block, which are branches, JMP, RTS, RTI and BRK. JSR
+/// doesn't end a block, since the subroutine returns to the next instruction.
+/// Bytes that don't decode to an instruction end the current block without any
+/// successors and aren't part of any block.
+pub fn analyze(memory: &Memory, start: Word, end: Word) -> Vec<BasicBlock> {
+    let mut instructions = Vec::new();
+    let mut address = start as u32;
+    while address <= end as u32 {
+        match decode_at(memory, address as Word) {
+            Some(decoded) => {
+                instructions.push(Some(decoded));
+                address += decoded.len as u32;
+            }
+            None => {
+                instructions.push(None);
+                address += 1;
+            }
+        }
+    }
+
+    let mut leaders = BTreeSet::from([start]);
+    for decoded in instructions.iter().flatten() {
+        leaders.extend(target(decoded));
+        if ends_block(decoded) {
+            leaders.insert(next_address(decoded));
+        }
+    }
+
+    let mut blocks: Vec<BasicBlock> = Vec::new();
+    let mut current: Option<BasicBlock> = None;
+    for decoded in instructions {
+        let Some(decoded) = decoded else {
+            blocks.extend(current.take());
+            continue;
+        };
+
+        if leaders.contains(&decoded.addr) {
+            if let Some(mut block) = current.take() {
+                block.successors.push(decoded.addr);
+                blocks.push(block);
+            }
+        }
+        let block = current.get_or_insert_with(|| BasicBlock {
+            start: decoded.addr,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+        });
+        block.instructions.push(decoded);
+
+        if ends_block(&decoded) {
+            if decoded.instruction.opcode.is_branch() {
+                block.successors.push(next_address(&decoded));
+            }
+            block.successors.extend(target(&decoded));
+            blocks.extend(current.take());
+        }
+    }
+    // the last block falls through to whatever follows the range
+    if let Some(mut block) = current {
+        if let Some(last) = block.instructions.last() {
+            block.successors.push(next_address(last));
+        }
+        blocks.push(block);
+    }
+    blocks
+}
+
+fn ends_block(decoded: &DecodedInstruction) -> bool {
+    let opcode = decoded.instruction.opcode;
+    opcode.is_branch() || opcode.is_return() || matches!(opcode, Opcode::Jmp | Opcode::Brk)
+}
+
+fn next_address(decoded: &DecodedInstruction) -> Word {
+    decoded.addr.wrapping_add(decoded.len as Word)
+}
+
+/// The statically known address that the instruction branches, jumps or calls to.
+fn target(decoded: &DecodedInstruction) -> Option<Word> {
+    let [low, high] = decoded.operands;
+    match (
+        decoded.instruction.opcode,
+        decoded.instruction.addressing_mode,
+    ) {
+        (opcode, AddressingMode::Relative) if opcode.is_branch() => {
+            Some(next_address(decoded).wrapping_add_signed(low as i8 as i16))
+        }
+        (Opcode::Jmp | Opcode::Jsr, AddressingMode::Absolute) => {
+            Some(Word::from_le_bytes([low, high]))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    /// The start, number of instructions and successors of each block.
+    fn summarize(blocks: &[BasicBlock]) -> Vec<(Word, usize, Vec<Word>)> {
+        blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.start,
+                    block.instructions.len(),
+                    block.successors.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_conditional_branch() {
+        let mut memory = Memory::new();
+        memory.load(
+            0xC000,
+            &[
+                0xA2, 0x05, // LDX #5
+                0xCA, // loop: DEX
+                0xD0, 0xFD, // BNE loop
+                0x60, // RTS
+            ],
+        );
+
+        let blocks = analyze(&memory, 0xC000, 0xC005);
+        assert_eq!(
+            summarize(&blocks),
+            vec![
+                (0xC000, 1, vec![0xC002]),
+                (0xC002, 2, vec![0xC005, 0xC002]),
+                (0xC005, 1, vec![]),
+            ]
+        );
+        assert_eq!(blocks[1].instructions[1].instruction.opcode, Opcode::Bne);
+    }
+
+    #[test]
+    fn test_jumps_and_invalid_opcodes() {
+        let mut memory = Memory::new();
+        memory.load(
+            0xC000,
+            &[
+                0x20, 0x08, 0xC0, // JSR sub
+                0x4C, 0x00, 0xC0, // JMP 0xC000
+                0x02, // invalid
+                0xEA, // NOP
+                0xE8, // sub: INX
+                0x6C, 0x34, 0x12, // JMP (0x1234)
+            ],
+        );
+
+        let blocks = analyze(&memory, 0xC000, 0xC00B);
+        assert_eq!(
+            summarize(&blocks),
+            vec![
+                (0xC000, 2, vec![0xC000]),
+                (0xC007, 1, vec![0xC008]),
+                (0xC008, 2, vec![]),
+            ]
+        );
+    }
+}
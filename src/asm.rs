@@ -0,0 +1,646 @@
+use std::collections::HashMap;
+
+use derive_more::{Display, Error};
+
+use crate::cpu::{Byte, Word, CODE_START, IRQ_VECTOR, NMI_VECTOR, RESET_VECTOR};
+use crate::mem::MAX_MEMORY;
+use crate::opcode::{AddressingMode, Instruction, Opcode};
+use crate::util::{relative_offset, OutOfRange};
+
+/// The output of the assembler: a contiguous block of bytes and the address it
+/// has to be loaded at (see [`Memory::load`](crate::mem::Memory::load)).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Program {
+    pub start: Word,
+    pub bytes: Vec<Byte>,
+    /// The addresses of all labels that the program defines.
+    pub labels: HashMap<String, Word>,
+}
+
+#[derive(Error, Display, Debug, Clone, Eq, PartialEq)]
+pub enum AssembleError {
+    #[display(fmt = "line {}: unknown mnemonic '{}'", line, mnemonic)]
+    UnknownMnemonic { line: usize, mnemonic: String },
+    #[display(fmt = "line {}: unknown directive '{}'", line, directive)]
+    UnknownDirective { line: usize, directive: String },
+    #[display(fmt = "line {}: invalid operand '{}'", line, operand)]
+    InvalidOperand { line: usize, operand: String },
+    #[display(
+        fmt = "line {}: {:?} does not support addressing mode {:?}",
+        line,
+        opcode,
+        addressing_mode
+    )]
+    UnsupportedAddressingMode {
+        line: usize,
+        opcode: Opcode,
+        addressing_mode: AddressingMode,
+    },
+    #[display(fmt = "line {}: undefined label '{}'", line, label)]
+    UndefinedLabel { line: usize, label: String },
+    #[display(fmt = "line {}: label '{}' is already defined", line, label)]
+    DuplicateLabel { line: usize, label: String },
+    #[display(fmt = "line {}: value {:#X} does not fit", line, value)]
+    ValueOutOfRange { line: usize, value: Word },
+    #[display(
+        fmt = "line {}: branch from {:#06X} to {:#06X} is {} bytes, which is out of range",
+        line,
+        from,
+        to,
+        distance
+    )]
+    BranchOutOfRange {
+        line: usize,
+        from: Word,
+        to: Word,
+        distance: i32,
+    },
+    #[display(
+        fmt = "line {}: .org {:#06X} is behind the current address",
+        line,
+        address
+    )]
+    OrgBackwards { line: usize, address: Word },
+    #[display(fmt = "line {}: program exceeds the address space", line)]
+    AddressOverflow { line: usize },
+}
+
+/// Assembles a single instruction that is located at `address`.
+pub fn assemble_line(line: &str, address: Word) -> Result<Vec<Byte>, AssembleError> {
+    assemble_at(line, address).map(|program| program.bytes)
+}
+
+/// Assembles a program in two passes, so that labels can be referenced before
+/// they are defined.
+///
+/// Supported are the `.org`, `.byte` and `.word` directives, labels (`name:`) and
+/// comments (`;`). Numbers can be written as `$FF`, `0xFF`, `%11111111` or `255`.
+/// If there is no leading `.org`, the program starts at [`CODE_START`].
+pub fn assemble(source: &str) -> Result<Program, AssembleError> {
+    assemble_at(source, CODE_START)
+}
+
+fn assemble_at(source: &str, origin: Word) -> Result<Program, AssembleError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut start = None;
+    let mut address = origin as u32;
+
+    // first pass: determine the size and address of every statement
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut line = line.split(';').next().unwrap_or_default().trim();
+
+        if let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if is_identifier(label) {
+                if labels.insert(label.to_string(), address as Word).is_some() {
+                    return Err(AssembleError::DuplicateLabel {
+                        line: line_number,
+                        label: label.to_string(),
+                    });
+                }
+                line = rest.trim();
+            }
+        }
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(line, line_number)?;
+        if let Statement::Org(org) = statement {
+            if start.is_none() {
+                start = Some(org);
+            } else if (org as u32) < address {
+                return Err(AssembleError::OrgBackwards {
+                    line: line_number,
+                    address: org,
+                });
+            }
+            address = org as u32;
+            continue;
+        }
+
+        start.get_or_insert(address as Word);
+        let size = statement.size() as u32;
+        statements.push((line_number, address as Word, statement));
+        address += size;
+        if address > 0x10000 {
+            return Err(AssembleError::AddressOverflow { line: line_number });
+        }
+    }
+
+    // second pass: emit the bytes with all labels resolved
+    let start = start.unwrap_or(origin);
+    let mut bytes = Vec::new();
+    for (line, address, statement) in statements {
+        bytes.resize((address - start) as usize, 0);
+        statement.emit(line, address, &labels, &mut bytes)?;
+    }
+
+    Ok(Program {
+        start,
+        bytes,
+        labels,
+    })
+}
+
+#[derive(Error, Display, Debug, Clone, Eq, PartialEq)]
+pub enum RomError {
+    #[display(fmt = "vector target '{}' is not a label of the program", label)]
+    UndefinedLabel { label: String },
+}
+
+/// What an interrupt vector points to, either an address or the name of a label.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VectorTarget {
+    Address(Word),
+    Label(String),
+}
+
+impl From<Word> for VectorTarget {
+    fn from(address: Word) -> Self {
+        VectorTarget::Address(address)
+    }
+}
+
+impl From<&str> for VectorTarget {
+    fn from(label: &str) -> Self {
+        VectorTarget::Label(label.to_string())
+    }
+}
+
+/// Builds a 64KB memory image out of assembled programs and the interrupt vectors,
+/// which can be loaded with [`Memory::load`](crate::mem::Memory::load) at `0x0000`.
+#[derive(Debug, Clone)]
+pub struct RomBuilder {
+    image: Vec<Byte>,
+    labels: HashMap<String, Word>,
+    vectors: Vec<(Word, VectorTarget)>,
+}
+
+impl Default for RomBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RomBuilder {
+    pub fn new() -> Self {
+        Self {
+            image: vec![0; MAX_MEMORY as usize + 1],
+            labels: HashMap::new(),
+            vectors: Vec::new(),
+        }
+    }
+
+    /// Places the program at its start address. Its labels can be used as vector
+    /// targets afterwards.
+    pub fn program(mut self, program: &Program) -> Self {
+        let start = program.start as usize;
+        self.image[start..start + program.bytes.len()].copy_from_slice(&program.bytes);
+        self.labels.extend(program.labels.clone());
+        self
+    }
+
+    pub fn reset_vector(self, target: impl Into<VectorTarget>) -> Self {
+        self.vector(RESET_VECTOR, target.into())
+    }
+
+    pub fn irq_vector(self, target: impl Into<VectorTarget>) -> Self {
+        self.vector(IRQ_VECTOR, target.into())
+    }
+
+    pub fn nmi_vector(self, target: impl Into<VectorTarget>) -> Self {
+        self.vector(NMI_VECTOR, target.into())
+    }
+
+    fn vector(mut self, vector: Word, target: VectorTarget) -> Self {
+        self.vectors.push((vector, target));
+        self
+    }
+
+    /// Returns the image with the vectors written little-endian to `0xFFFA-0xFFFF`.
+    pub fn build(&self) -> Result<Vec<Byte>, RomError> {
+        let mut image = self.image.clone();
+        for (vector, target) in &self.vectors {
+            let address = match target {
+                VectorTarget::Address(address) => *address,
+                VectorTarget::Label(label) => {
+                    *self
+                        .labels
+                        .get(label)
+                        .ok_or_else(|| RomError::UndefinedLabel {
+                            label: label.clone(),
+                        })?
+                }
+            };
+            let vector = *vector as usize;
+            image[vector..vector + 2].copy_from_slice(&address.to_le_bytes());
+        }
+        Ok(image)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Value {
+    Number(Word),
+    Label(String),
+}
+
+impl Value {
+    fn resolve(&self, line: usize, labels: &HashMap<String, Word>) -> Result<Word, AssembleError> {
+        match self {
+            Value::Number(number) => Ok(*number),
+            Value::Label(label) => {
+                labels
+                    .get(label)
+                    .copied()
+                    .ok_or_else(|| AssembleError::UndefinedLabel {
+                        line,
+                        label: label.clone(),
+                    })
+            }
+        }
+    }
+
+    fn resolve_byte(
+        &self,
+        line: usize,
+        labels: &HashMap<String, Word>,
+    ) -> Result<Byte, AssembleError> {
+        let value = self.resolve(line, labels)?;
+        Byte::try_from(value).map_err(|_| AssembleError::ValueOutOfRange { line, value })
+    }
+
+    fn is_zero_page(&self) -> bool {
+        matches!(self, Value::Number(number) if *number <= 0xFF)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Value),
+    Direct(Value),
+    DirectX(Value),
+    DirectY(Value),
+    Indirect(Value),
+    IndexedIndirect(Value),
+    IndirectIndexed(Value),
+}
+
+impl Operand {
+    fn value(&self) -> Option<&Value> {
+        match self {
+            Operand::None | Operand::Accumulator => None,
+            Operand::Immediate(value)
+            | Operand::Direct(value)
+            | Operand::DirectX(value)
+            | Operand::DirectY(value)
+            | Operand::Indirect(value)
+            | Operand::IndexedIndirect(value)
+            | Operand::IndirectIndexed(value) => Some(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Statement {
+    Instruction(Instruction, Operand),
+    Bytes(Vec<Value>),
+    Words(Vec<Value>),
+    Org(Word),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction(instruction, _) => instruction.len(),
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => values.len() * 2,
+            Statement::Org(_) => 0,
+        }
+    }
+
+    fn emit(
+        &self,
+        line: usize,
+        address: Word,
+        labels: &HashMap<String, Word>,
+        bytes: &mut Vec<Byte>,
+    ) -> Result<(), AssembleError> {
+        match self {
+            Statement::Instruction(instruction, operand) => {
+                // the addressing mode was validated when the statement was parsed
+                bytes.push(instruction.encode().unwrap());
+                let Some(value) = operand.value() else {
+                    return Ok(());
+                };
+                match instruction.addressing_mode {
+                    AddressingMode::Relative => {
+                        // the offset is relative to the instruction following the branch
+                        let target = value.resolve(line, labels)?;
+                        let offset = relative_offset(address.wrapping_add(2), target).map_err(
+                            |OutOfRange { distance }| AssembleError::BranchOutOfRange {
+                                line,
+                                from: address,
+                                to: target,
+                                distance: distance as i32,
+                            },
+                        )?;
+                        bytes.push(offset as Byte);
+                    }
+                    mode if mode.operand_len() == 1 => {
+                        bytes.push(value.resolve_byte(line, labels)?)
+                    }
+                    _ => bytes.extend_from_slice(&value.resolve(line, labels)?.to_le_bytes()),
+                }
+            }
+            Statement::Bytes(values) => {
+                for value in values {
+                    bytes.push(value.resolve_byte(line, labels)?);
+                }
+            }
+            Statement::Words(values) => {
+                for value in values {
+                    bytes.extend_from_slice(&value.resolve(line, labels)?.to_le_bytes());
+                }
+            }
+            Statement::Org(_) => {}
+        }
+        Ok(())
+    }
+}
+
+fn parse_statement(line: &str, line_number: usize) -> Result<Statement, AssembleError> {
+    let (head, rest) = match line.split_once(char::is_whitespace) {
+        Some((head, rest)) => (head, rest.trim()),
+        None => (line, ""),
+    };
+
+    if let Some(directive) = head.strip_prefix('.') {
+        let values = || {
+            rest.split(',')
+                .map(|value| parse_value(value.trim(), line_number))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        return match directive.to_ascii_lowercase().as_str() {
+            "org" => match parse_value(rest, line_number)? {
+                Value::Number(address) => Ok(Statement::Org(address)),
+                Value::Label(_) => Err(AssembleError::InvalidOperand {
+                    line: line_number,
+                    operand: rest.to_string(),
+                }),
+            },
+            "byte" => Ok(Statement::Bytes(values()?)),
+            "word" => Ok(Statement::Words(values()?)),
+            _ => Err(AssembleError::UnknownDirective {
+                line: line_number,
+                directive: head.to_string(),
+            }),
+        };
+    }
+
+    let opcode = Opcode::from_mnemonic(head).ok_or_else(|| AssembleError::UnknownMnemonic {
+        line: line_number,
+        mnemonic: head.to_string(),
+    })?;
+    let operand = parse_operand(rest, line_number)?;
+    let addressing_mode = select_addressing_mode(opcode, &operand);
+    let instruction = Instruction::new(opcode, addressing_mode);
+    if instruction.encode().is_none() {
+        return Err(AssembleError::UnsupportedAddressingMode {
+            line: line_number,
+            opcode,
+            addressing_mode,
+        });
+    }
+    Ok(Statement::Instruction(instruction, operand))
+}
+
+fn select_addressing_mode(opcode: Opcode, operand: &Operand) -> AddressingMode {
+    let supports = |mode| Instruction::new(opcode, mode).encode().is_some();
+    let zero_page_or = |value: &Value, zero_page, absolute| {
+        if value.is_zero_page() && supports(zero_page) {
+            zero_page
+        } else {
+            absolute
+        }
+    };
+
+    match operand {
+        Operand::None if supports(AddressingMode::Accumulator) => AddressingMode::Accumulator,
+        Operand::None => AddressingMode::Implicit,
+        Operand::Accumulator => AddressingMode::Accumulator,
+        Operand::Immediate(_) => AddressingMode::Immediate,
+        Operand::Direct(_) if opcode.is_branch() => AddressingMode::Relative,
+        Operand::Direct(value) => {
+            zero_page_or(value, AddressingMode::ZeroPage, AddressingMode::Absolute)
+        }
+        Operand::DirectX(value) => {
+            zero_page_or(value, AddressingMode::ZeroPageX, AddressingMode::AbsoluteX)
+        }
+        Operand::DirectY(value) => {
+            zero_page_or(value, AddressingMode::ZeroPageY, AddressingMode::AbsoluteY)
+        }
+        Operand::Indirect(_) => AddressingMode::Indirect,
+        Operand::IndexedIndirect(_) => AddressingMode::IndexedIndirect,
+        Operand::IndirectIndexed(_) => AddressingMode::IndirectIndexed,
+    }
+}
+
+fn parse_operand(operand: &str, line: usize) -> Result<Operand, AssembleError> {
+    let operand = operand
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let upper = operand.to_ascii_uppercase();
+    let inner =
+        |prefix: usize, suffix: usize| parse_value(&operand[prefix..operand.len() - suffix], line);
+
+    Ok(if operand.is_empty() {
+        Operand::None
+    } else if upper == "A" {
+        Operand::Accumulator
+    } else if operand.starts_with('#') {
+        Operand::Immediate(inner(1, 0)?)
+    } else if operand.starts_with('(') && upper.ends_with(",X)") {
+        Operand::IndexedIndirect(inner(1, 3)?)
+    } else if operand.starts_with('(') && upper.ends_with("),Y") {
+        Operand::IndirectIndexed(inner(1, 3)?)
+    } else if operand.starts_with('(') && operand.ends_with(')') {
+        Operand::Indirect(inner(1, 1)?)
+    } else if upper.ends_with(",X") {
+        Operand::DirectX(inner(0, 2)?)
+    } else if upper.ends_with(",Y") {
+        Operand::DirectY(inner(0, 2)?)
+    } else {
+        Operand::Direct(inner(0, 0)?)
+    })
+}
+
+fn parse_value(value: &str, line: usize) -> Result<Value, AssembleError> {
+    if is_identifier(value) {
+        return Ok(Value::Label(value.to_string()));
+    }
+
+    let number = if let Some(hex) = value.strip_prefix('$') {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(hex) = value.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(binary) = value.strip_prefix('%') {
+        u32::from_str_radix(binary, 2)
+    } else {
+        value.parse::<u32>()
+    };
+
+    number
+        .ok()
+        .and_then(|number| Word::try_from(number).ok())
+        .map(Value::Number)
+        .ok_or_else(|| AssembleError::InvalidOperand {
+            line,
+            operand: value.to_string(),
+        })
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Cpu;
+    use crate::mem::Memory;
+
+    #[test]
+    fn test_assemble_labeled_loop() {
+        let program = assemble(
+            "
+            .org $C000
+                LDX #$03
+            loop:
+                DEX
+                BNE loop   ; branches backwards
+                JMP end
+            end: NOP
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(program.start, 0xC000);
+        assert_eq!(
+            program.bytes,
+            vec![
+                0xA2, 0x03, // LDX #$03
+                0xCA, // DEX
+                0xD0, 0xFD, // BNE loop
+                0x4C, 0x08, 0xC0, // JMP end
+                0xEA, // NOP
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_directives() {
+        let program = assemble(
+            "
+            .org $0200
+            data: .byte 1, $02, %11
+                  .word $1234, data
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(program.start, 0x0200);
+        assert_eq!(
+            program.bytes,
+            vec![0x01, 0x02, 0x03, 0x34, 0x12, 0x00, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_assemble_branch_out_of_range() {
+        let result = assemble(
+            "
+            start:
+                BEQ far
+                .org $C100
+            far: NOP
+            ",
+        );
+
+        assert_eq!(
+            result,
+            Err(AssembleError::BranchOutOfRange {
+                line: 3,
+                from: 0xC000,
+                to: 0xC100,
+                distance: 254,
+            })
+        );
+    }
+
+    #[test]
+    fn test_rom_builder() {
+        let program = assemble(
+            "
+            .org $8000
+            start:
+                LDA #$01
+            handler:
+                RTI
+            ",
+        )
+        .unwrap();
+
+        let image = RomBuilder::new()
+            .program(&program)
+            .reset_vector("start")
+            .irq_vector("handler")
+            .nmi_vector(0x1234)
+            .build()
+            .unwrap();
+        assert_eq!(image.len(), 0x10000);
+        assert_eq!(image[0x8000..0x8003], [0xA9, 0x01, 0x40]);
+        assert_eq!(image[0xFFFA..], [0x34, 0x12, 0x00, 0x80, 0x02, 0x80]);
+
+        let mut memory = Memory::new();
+        memory.load(0x0000, &image);
+        let mut cpu = Cpu::new(memory);
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x8000);
+
+        assert_eq!(
+            RomBuilder::new().reset_vector("start").build(),
+            Err(RomError::UndefinedLabel {
+                label: "start".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_assemble_line() {
+        assert_eq!(assemble_line("LDA $12", 0).unwrap(), vec![0xA5, 0x12]);
+        assert_eq!(
+            assemble_line("LDA $1234,X", 0).unwrap(),
+            vec![0xBD, 0x34, 0x12]
+        );
+        assert_eq!(assemble_line("STA ($20),Y", 0).unwrap(), vec![0x91, 0x20]);
+        assert_eq!(assemble_line("ASL", 0).unwrap(), vec![0x0A]);
+        assert_eq!(
+            assemble_line("STX $1234,Y", 0),
+            Err(AssembleError::UnsupportedAddressingMode {
+                line: 1,
+                opcode: Opcode::Stx,
+                addressing_mode: AddressingMode::AbsoluteY,
+            })
+        );
+    }
+}
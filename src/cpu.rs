@@ -1,7 +1,17 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
 use bitflags::bitflags;
+use derive_more::Display;
+#[cfg(feature = "std")]
+use derive_more::Error;
 
-use crate::mem::Memory;
+use crate::mem::{BusCycle, Memory};
 use crate::opcode::*;
+use crate::trace::{Divergence, TraceRecord};
+#[cfg(feature = "std")]
+use crate::trace::{TraceCallback, TraceEntry};
 
 pub type Byte = u8;
 pub type Word = u16;
@@ -10,7 +20,9 @@ pub type DoubleWord = u32;
 pub const CODE_START: Word = 0xC000;
 pub const STACK_START: Word = 0x0100;
 pub const STACK_END: Word = 0x01FF;
+pub const NMI_VECTOR: Word = 0xFFFA;
 pub const RESET_VECTOR: Word = 0xFFFC;
+pub const IRQ_VECTOR: Word = 0xFFFE;
 
 bitflags! {
     #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -26,12 +38,258 @@ bitflags! {
     }
 }
 
-pub enum CycleRestriction {
-    None,
-    Some(usize),
+/// Combinators for building a status from individual flags, e.g.
+/// `ProcessorStatus::empty().with_carry(true).with_negative(true)`.
+impl ProcessorStatus {
+    pub fn with_carry(self, value: bool) -> Self {
+        self.with(Self::Carry, value)
+    }
+
+    pub fn with_zero(self, value: bool) -> Self {
+        self.with(Self::Zero, value)
+    }
+
+    pub fn with_interrupt_disable(self, value: bool) -> Self {
+        self.with(Self::InterruptDisable, value)
+    }
+
+    pub fn with_decimal_mode(self, value: bool) -> Self {
+        self.with(Self::DecimalMode, value)
+    }
+
+    pub fn with_break(self, value: bool) -> Self {
+        self.with(Self::Break, value)
+    }
+
+    pub fn with_overflow(self, value: bool) -> Self {
+        self.with(Self::Overflow, value)
+    }
+
+    pub fn with_negative(self, value: bool) -> Self {
+        self.with(Self::Negative, value)
+    }
+
+    fn with(mut self, flag: Self, value: bool) -> Self {
+        self.set(flag, value);
+        self
+    }
+}
+
+/// Renders the flags from bit 7 to bit 0 as `NV-BDIZC`, with a dash for each flag
+/// that is clear. The unused bit is always a dash.
+impl core::fmt::Display for ProcessorStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        const LETTERS: [(ProcessorStatus, char); 8] = [
+            (ProcessorStatus::Negative, 'N'),
+            (ProcessorStatus::Overflow, 'V'),
+            (ProcessorStatus::_Unused, '-'),
+            (ProcessorStatus::Break, 'B'),
+            (ProcessorStatus::DecimalMode, 'D'),
+            (ProcessorStatus::InterruptDisable, 'I'),
+            (ProcessorStatus::Zero, 'Z'),
+            (ProcessorStatus::Carry, 'C'),
+        ];
+        for (flag, letter) in LETTERS {
+            let set = self.contains(flag);
+            f.write_char(if set { letter } else { '-' })?;
+        }
+        Ok(())
+    }
+}
+
+/// How long [`Cpu::run`] executes instructions for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RunLimit {
+    /// Execute the given number of instructions.
+    Instructions(usize),
+    /// Execute instructions until at least the given number of cycles has passed.
+    /// The last instruction is always completed, so it may overshoot the limit.
+    Cycles(u64),
+    /// Execute instructions until something else stops execution, like a trap.
+    Forever,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Shift {
+    Left,
+    Right,
+}
+
+/// The reason why [`Cpu::run`] stopped executing instructions.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StopReason {
+    /// The requested number of instructions has been executed.
+    InstructionLimit,
+    /// The requested number of cycles has passed.
+    CycleLimit,
+    /// The instruction at the given address jumped or branched to itself, so the
+    /// CPU would execute it forever.
+    Trap(Word),
+    /// The PC reached the address that execution should stop at.
+    PcReached(Word),
+    /// The subroutine or interrupt handler returned to the given address, see
+    /// [`Cpu::run_until_return`].
+    Returned(Word),
+    /// The last instruction wrote `value` into a read-only region at `addr` that
+    /// traps on writes, see [`RomWritePolicy::Trap`](crate::mem::RomWritePolicy::Trap).
+    RomWrite { addr: Word, value: Byte },
+    /// The next instruction couldn't be executed, see [`Cpu::step`]. Nothing of
+    /// it was executed, so the CPU is still in front of it.
+    Error(CpuError),
 }
 
-#[derive(Debug)]
+/// What a call to [`Cpu::run`] or one of its variants did before it stopped.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RunSummary {
+    pub reason: StopReason,
+    /// The number of instructions executed by this call, as counted towards
+    /// [`RunLimit::Instructions`].
+    pub instructions: usize,
+    /// The number of cycles that passed during this call.
+    pub cycles: u64,
+}
+
+/// An instruction that [`Cpu::step`] couldn't execute.
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum CpuError {
+    /// The byte at `address` doesn't decode to an instruction, so the CPU jams.
+    #[display(fmt = "invalid opcode {:#04X} at {:#06X}", opcode, address)]
+    InvalidOpcode { address: Word, opcode: Byte },
+    /// The instruction at `address` was decoded with an addressing mode that it
+    /// can't be executed with, which means that the decode table is broken.
+    #[display(fmt = "can't execute {:?} at {:#06X}", instruction, address)]
+    InternalDecode {
+        address: Word,
+        instruction: Instruction,
+    },
+}
+
+/// What [`Cpu::step`] does with a byte that doesn't decode to an instruction.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum InvalidOpcodePolicy {
+    /// Panics with a dump of the registers.
+    Panic,
+    /// Returns [`CpuError::InvalidOpcode`] without executing anything.
+    #[default]
+    Error,
+    /// Executes the byte as a one byte NOP that takes 2 cycles, so execution goes on
+    /// with the next byte.
+    NopSkip,
+}
+
+/// What an invalid opcode is executed as with [`InvalidOpcodePolicy::NopSkip`].
+const NOP_SKIP: OpInfo = OpInfo {
+    opcode: Opcode::Nop,
+    addressing_mode: AddressingMode::Implicit,
+    bytes: 1,
+    cycles: 2,
+    undocumented: true,
+};
+
+/// An internal invariant of the CPU that doesn't hold, see [`Cpu::validate`].
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InvariantViolation {
+    #[display(fmt = "the unused status bit is cleared")]
+    UnusedFlagCleared,
+    /// The break flag only exists in status bytes on the stack.
+    #[display(fmt = "the break flag is set in the status register")]
+    BreakFlagSet,
+    #[display(fmt = "{:?} is in flight, but isn't cycle stepped", _0)]
+    NotCycleStepped(Instruction),
+}
+
+/// The instruction executed by [`Cpu::step`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StepResult {
+    /// The address of the instruction.
+    pub pc: Word,
+    pub instruction: Instruction,
+    /// The number of cycles the step took, including those of a serviced interrupt.
+    pub cycles: u64,
+    /// Whether an IRQ was serviced before the instruction, which is then the first
+    /// instruction of the handler.
+    pub interrupted: bool,
+}
+
+/// The registers together with the zero page and the stack, see
+/// [`Cpu::quick_snapshot`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct QuickSnapshot {
+    pub pc: Word,
+    pub sp: Byte,
+    pub a: Byte,
+    pub x: Byte,
+    pub y: Byte,
+    pub status: ProcessorStatus,
+    pub cycles: u64,
+    /// The memory from `0x0000` to [`STACK_END`].
+    pub memory: [Byte; STACK_END as usize + 1],
+}
+
+/// The registers together with the RAM, see [`Cpu::snapshot`]. Clones of a
+/// snapshot share the RAM.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CpuSnapshot {
+    pub pc: Word,
+    pub sp: Byte,
+    pub a: Byte,
+    pub x: Byte,
+    pub y: Byte,
+    pub status: ProcessorStatus,
+    pub cycles: u64,
+    /// The RAM, as seen through [`Memory::slice`], so without banks.
+    pub memory: Arc<[Byte]>,
+}
+
+/// An instruction that is being executed cycle by cycle through [`Cpu::tick`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct InFlight {
+    instruction: Instruction,
+    /// The number of cycles of this instruction that have already been performed.
+    cycle: u8,
+    /// The operand address assembled so far.
+    address: Word,
+}
+
+/// The state before the last instruction, which [`Cpu::undo_last`] restores.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Undo {
+    pc: Word,
+    sp: Byte,
+    a: Byte,
+    x: Byte,
+    y: Byte,
+    status: ProcessorStatus,
+    cycles: u64,
+    polled_interrupt_disable: Option<bool>,
+    /// The addresses written by the instruction together with their previous values,
+    /// in the order they were written.
+    writes: Vec<(Word, Byte)>,
+}
+
+/// Holds the trace callback, which is dropped when the CPU is cloned.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Tracer(Option<TraceCallback>);
+
+#[cfg(feature = "std")]
+impl Clone for Tracer {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Tracer").finish()
+    }
+}
+
+/// Cloning a CPU clones its memory, see [`Memory`]'s `Clone` for the caveats. The
+/// trace callback isn't cloned either.
+#[derive(Debug, Clone)]
 pub struct Cpu {
     pub memory: Memory,
 
@@ -41,6 +299,47 @@ pub struct Cpu {
     pub x: Byte,
     pub y: Byte,
     pub status: ProcessorStatus,
+
+    /// The number of cycles that have been executed so far.
+    pub cycles: u64,
+
+    irq_line: bool,
+    /// The interrupt disable flag as seen by the next interrupt poll, if it differs
+    /// from the live flag. CLI, SEI and PLP change the flag only after the poll, so
+    /// their effect on interrupts is delayed by one instruction.
+    polled_interrupt_disable: Option<bool>,
+
+    /// Whether [`Cpu::run`] stops with [`StopReason::Trap`] when an instruction
+    /// leaves the PC unchanged, as test ROMs do to signal completion.
+    pub detect_traps: bool,
+
+    /// The CPU model, which determines how opcodes are decoded.
+    pub variant: Variant,
+
+    /// What to do with invalid opcodes. [`Cpu::run`] panics on them unless they are
+    /// skipped.
+    pub on_invalid: InvalidOpcodePolicy,
+
+    /// Whether servicing an interrupt counts towards [`RunLimit::Instructions`] like
+    /// an instruction does. By default it doesn't, so the limit is the number of
+    /// instructions that are actually executed, while the cycles of the interrupt
+    /// entry count towards [`RunLimit::Cycles`] either way.
+    pub interrupts_count_as_instructions: bool,
+
+    /// Whether [`Cpu::step`] checks [`Cpu::validate`] after every instruction and
+    /// panics if an invariant is violated.
+    pub debug_validate: bool,
+
+    in_flight: Option<InFlight>,
+    /// The remaining cycles of an instruction that [`Cpu::clock`] has already
+    /// executed as a whole.
+    clock_pending: u64,
+    undo: Option<Undo>,
+    last_instruction: Option<DecodedInstruction>,
+    /// Set when an operand address is resolved for an addressing mode without one.
+    decode_failed: bool,
+    #[cfg(feature = "std")]
+    tracer: Tracer,
 }
 
 impl Cpu {
@@ -53,44 +352,777 @@ impl Cpu {
             a: 0,
             x: 0,
             y: 0,
-            status: ProcessorStatus::empty(),
+            status: ProcessorStatus::_Unused,
+
+            cycles: 0,
+
+            irq_line: false,
+            polled_interrupt_disable: None,
+
+            detect_traps: false,
+
+            variant: Variant::default(),
+
+            on_invalid: InvalidOpcodePolicy::default(),
+
+            interrupts_count_as_instructions: false,
+
+            debug_validate: false,
+
+            in_flight: None,
+            clock_pending: 0,
+            undo: None,
+            last_instruction: None,
+            decode_failed: false,
+            #[cfg(feature = "std")]
+            tracer: Tracer::default(),
         }
     }
 
-    pub fn run(&mut self, instruction_limit: Option<usize>) {
+    /// Creates a CPU in the state it is in after powering on, which starts at the
+    /// address in the reset vector with SP = 0xFD and interrupts disabled. Unlike
+    /// [`Cpu::new`], which starts at [`CODE_START`], this respects the vectors of a
+    /// loaded ROM.
+    pub fn new_reset(memory: Memory) -> Self {
+        let mut cpu = Self::new(memory);
+        // the SP is 0x00 on power-on, and the reset sequence decrements it by 3
+        cpu.sp = 0x00;
+        cpu.reset();
+        cpu
+    }
+
+    pub fn run(&mut self, limit: RunLimit) -> RunSummary {
         #[cfg(feature = "trace")]
         {
-            println!("addr op ins |AC XR YR SP|nv_bdizc|");
-            println!("------------|-----------|--------|");
+            println!("addr op ins |AC XR YR SP STCK|NV-BDIZC|");
+            println!("------------|----------------|--------|");
         }
 
-        if let Some(limit) = instruction_limit {
-            for _ in 0..limit {
-                self.execute_next_instruction();
+        self.run_until(limit, |_| None)
+    }
+
+    /// Executes instructions until the PC equals `target`, at most `max_instructions`
+    /// of them. This is what a debugger's "run to cursor" does.
+    pub fn run_until_pc(&mut self, target: Word, max_instructions: usize) -> RunSummary {
+        self.run_until(RunLimit::Instructions(max_instructions), |cpu| {
+            (cpu.pc == target).then_some(StopReason::PcReached(target))
+        })
+    }
+
+    /// Executes instructions until the subroutine or interrupt handler that is
+    /// currently executing returns, at most `max_instructions` of them. A return is
+    /// detected by the SP rising above its current value, so nested calls are
+    /// stepped through. The SP is compared with wrapping, so the stack may wrap
+    /// around as long as it doesn't grow by more than 127 bytes.
+    pub fn run_until_return(&mut self, max_instructions: usize) -> RunSummary {
+        let sp = self.sp;
+        self.run_until(RunLimit::Instructions(max_instructions), |cpu| {
+            (cpu.sp.wrapping_sub(sp) as i8 > 0).then_some(StopReason::Returned(cpu.pc))
+        })
+    }
+
+    /// Executes instructions until either `stop` returns a reason before an
+    /// instruction, the limit is reached, a trap is detected or an instruction
+    /// fails.
+    fn run_until(
+        &mut self,
+        limit: RunLimit,
+        stop: impl Fn(&Cpu) -> Option<StopReason>,
+    ) -> RunSummary {
+        let start_cycles = self.cycles;
+        let mut executed = 0;
+        let reason = loop {
+            if let Some(reason) = stop(self) {
+                break reason;
+            }
+            match limit {
+                RunLimit::Instructions(limit) if executed >= limit => {
+                    break StopReason::InstructionLimit
+                }
+                RunLimit::Cycles(limit) if self.cycles - start_cycles >= limit => {
+                    break StopReason::CycleLimit
+                }
+                _ => {}
+            }
+
+            let pc = self.pc;
+            let step = match self.step() {
+                Ok(step) => step,
+                Err(error) => break StopReason::Error(error),
+            };
+            executed += 1;
+            if step.interrupted && self.interrupts_count_as_instructions {
+                executed += 1;
+            }
+
+            if let Some((addr, value)) = self.memory.take_rom_write() {
+                break StopReason::RomWrite { addr, value };
+            }
+
+            // an instruction that doesn't change the PC will be executed over and over again
+            if self.detect_traps && self.pc == pc {
+                break StopReason::Trap(pc);
             }
+        };
+
+        RunSummary {
+            reason,
+            instructions: executed,
+            cycles: self.cycles - start_cycles,
+        }
+    }
+
+    /// Performs the reset sequence, which takes 7 cycles: the SP is decremented by 3
+    /// without writing to the stack, interrupts are disabled and execution continues
+    /// at the address in the reset vector.
+    ///
+    /// Like an interrupt, the sequence goes through the three pushes, but they are
+    /// turned into reads, so the stack is read at the three addresses before the
+    /// vector is fetched. Nothing is written.
+    pub fn reset(&mut self) {
+        for _ in 0..3 {
+            self.memory.read(self.stack_addr());
+            self.sp = self.sp.wrapping_sub(1);
+        }
+        self.status.insert(ProcessorStatus::InterruptDisable);
+        let low_byte = self.memory.read(RESET_VECTOR);
+        let high_byte = self.memory.read(RESET_VECTOR + 1);
+        self.pc = Word::from_le_bytes([low_byte, high_byte]);
+        self.cycles += 7;
+    }
+
+    pub fn set_pc(&mut self, pc: Word) {
+        self.pc = pc;
+    }
+
+    pub fn set_sp(&mut self, sp: Byte) {
+        self.sp = sp;
+    }
+
+    pub fn set_a(&mut self, a: Byte) {
+        self.a = a;
+    }
+
+    pub fn set_x(&mut self, x: Byte) {
+        self.x = x;
+    }
+
+    pub fn set_y(&mut self, y: Byte) {
+        self.y = y;
+    }
+
+    /// Sets the status register. The unused bit always reads as set on the 6502,
+    /// so it is set regardless of `status`. The break flag only exists in the
+    /// copies of the status that are pushed to the stack, so it is dropped.
+    pub fn set_status(&mut self, status: ProcessorStatus) {
+        self.status = (status - ProcessorStatus::Break) | ProcessorStatus::_Unused;
+    }
+
+    /// The instruction that was executed most recently, or started if it's still in
+    /// flight (see [`Cpu::tick`]).
+    pub fn last_instruction(&self) -> Option<DecodedInstruction> {
+        self.last_instruction
+    }
+
+    /// Asserts or releases the IRQ line. While it or any device (see
+    /// [`Device::irq`](crate::mem::Device::irq)) asserts it and interrupts are
+    /// enabled, an IRQ is taken before the next instruction.
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    /// The address stored in the reset vector at [`RESET_VECTOR`].
+    pub fn reset_vector(&self) -> Word {
+        self.peek_vector(RESET_VECTOR)
+    }
+
+    /// The address stored in the IRQ/BRK vector at [`IRQ_VECTOR`].
+    pub fn irq_vector(&self) -> Word {
+        self.peek_vector(IRQ_VECTOR)
+    }
+
+    /// The address stored in the NMI vector at [`NMI_VECTOR`].
+    pub fn nmi_vector(&self) -> Word {
+        self.peek_vector(NMI_VECTOR)
+    }
+
+    fn peek_vector(&self, vector: Word) -> Word {
+        Word::from_le_bytes([self.memory.peek(vector), self.memory.peek(vector + 1)])
+    }
+
+    /// Computes the memory address the instruction at the PC will operate on, without
+    /// executing it. Returns `None` for instructions that don't access memory through
+    /// an operand, and for invalid opcodes.
+    pub fn effective_address(&self) -> Option<Word> {
+        let info = self.variant.opcodes()[self.memory.peek(self.pc) as usize]?;
+        match info.addressing_mode {
+            AddressingMode::Implicit | AddressingMode::Accumulator | AddressingMode::Immediate => {
+                None
+            }
+            mode => {
+                let operand = self.pc.wrapping_add(1);
+                Some(decode_operand(&self.memory, operand, self.x, self.y, mode).0)
+            }
+        }
+    }
+
+    /// Reverts the last instruction executed by [`Cpu::run`] and friends, restoring
+    /// the registers and the memory it wrote. Returns whether there was anything to
+    /// undo.
+    ///
+    /// Only a single instruction can be undone; the previous ones are lost. Also,
+    /// instructions that were executed through [`Cpu::tick`] can't be undone, and
+    /// side effects of writes, like output, aren't reverted.
+    pub fn undo_last(&mut self) -> bool {
+        let Some(undo) = self.undo.take() else {
+            return false;
+        };
+        for &(address, value) in undo.writes.iter().rev() {
+            self.memory.poke(address, value);
+        }
+        self.pc = undo.pc;
+        self.sp = undo.sp;
+        self.a = undo.a;
+        self.x = undo.x;
+        self.y = undo.y;
+        self.status = undo.status;
+        self.cycles = undo.cycles;
+        self.polled_interrupt_disable = undo.polled_interrupt_disable;
+        true
+    }
+
+    /// Captures the registers and the zero page and stack (`0x0000-0x01FF`), which
+    /// is cheap enough to do on every step. Memory above the stack isn't captured.
+    pub fn quick_snapshot(&self) -> QuickSnapshot {
+        let mut memory = [0; STACK_END as usize + 1];
+        for (address, byte) in (0..=STACK_END).zip(memory.iter_mut()) {
+            *byte = self.memory.peek(address);
+        }
+
+        QuickSnapshot {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.status,
+            cycles: self.cycles,
+            memory,
+        }
+    }
+
+    /// Restores a [`QuickSnapshot`]. Memory above the stack is left as it is.
+    pub fn restore_quick(&mut self, snapshot: &QuickSnapshot) {
+        for (address, &byte) in (0..=STACK_END).zip(snapshot.memory.iter()) {
+            self.memory.poke(address, byte);
+        }
+        self.pc = snapshot.pc;
+        self.sp = snapshot.sp;
+        self.a = snapshot.a;
+        self.x = snapshot.x;
+        self.y = snapshot.y;
+        self.set_status(snapshot.status);
+        self.cycles = snapshot.cycles;
+    }
+
+    /// Captures the registers and a copy of the RAM. Unlike
+    /// [`Cpu::quick_snapshot`], this covers the whole RAM, so it's meant for handing
+    /// the state to code that must not run the CPU, like a debugger UI.
+    pub fn snapshot(&self) -> CpuSnapshot {
+        self.snapshot_with(Arc::from(&*self.memory.ram()))
+    }
+
+    /// Like [`Cpu::snapshot`], but shares the RAM with the CPU instead of copying
+    /// it. The RAM is only copied once the CPU writes to it, so this is cheap if the
+    /// snapshot is short-lived or the CPU doesn't continue.
+    pub fn snapshot_cow(&self) -> CpuSnapshot {
+        self.snapshot_with(self.memory.ram())
+    }
+
+    fn snapshot_with(&self, memory: Arc<[Byte]>) -> CpuSnapshot {
+        CpuSnapshot {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.status,
+            cycles: self.cycles,
+            memory,
+        }
+    }
+
+    /// Restores a [`CpuSnapshot`], including the size of the RAM. An instruction
+    /// that is in flight through [`Cpu::tick`] is abandoned, and [`Cpu::undo_last`]
+    /// has nothing to undo.
+    pub fn restore(&mut self, snapshot: &CpuSnapshot) {
+        self.memory.set_ram(snapshot.memory.clone());
+        self.pc = snapshot.pc;
+        self.sp = snapshot.sp;
+        self.a = snapshot.a;
+        self.x = snapshot.x;
+        self.y = snapshot.y;
+        self.set_status(snapshot.status);
+        self.cycles = snapshot.cycles;
+        self.in_flight = None;
+        self.clock_pending = 0;
+        self.undo = None;
+    }
+
+    /// Predicts how many cycles the instruction at the PC will take, including the
+    /// page crossing penalty of indexed reads, without executing it.
+    ///
+    /// Whether a branch is taken is only known once it executes, so branches are
+    /// predicted as not taken. Invalid opcodes are predicted to take 0 cycles.
+    pub fn next_instruction_cycles(&self) -> u8 {
+        let Some(info) = self.variant.opcodes()[self.memory.peek(self.pc) as usize] else {
+            return 0;
+        };
+        if !info.opcode.has_page_cross_penalty() {
+            return info.cycles;
+        }
+
+        let operand = self.pc.wrapping_add(1);
+        let (_, _, page_crossed) = resolve_operand(
+            |address| self.memory.peek(address),
+            operand,
+            self.x,
+            self.y,
+            info.addressing_mode,
+        );
+        info.cycles + page_crossed as u8
+    }
+
+    /// Advances the PC past the instruction at the PC without executing it, as a
+    /// monitor's "skip" command does. The registers, flags, memory and cycle count
+    /// are left untouched. Returns the skipped instruction, or `None` without moving
+    /// the PC if the opcode is invalid.
+    ///
+    /// An instruction that was started with [`Cpu::tick`] is finished first, like
+    /// [`Cpu::step`] does. Skipping can't be undone, and afterwards
+    /// [`Cpu::undo_last`] has nothing to undo.
+    pub fn skip_instruction(&mut self) -> Option<Instruction> {
+        self.finish_in_flight();
+        let info = self.decode(self.memory.peek(self.pc))?;
+        self.pc = self.pc.wrapping_add(info.bytes as Word);
+        self.undo = None;
+        Some(info.instruction())
+    }
+
+    /// Returns the current state of the CPU as a trace record.
+    pub fn trace_record(&self) -> TraceRecord {
+        TraceRecord {
+            pc: self.pc,
+            opcode: self.memory.peek(self.pc),
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.sp,
+            status: self.status,
+            cycles: self.cycles,
+        }
+    }
+
+    /// Calls `callback` with the state of the CPU and the disassembled instruction
+    /// before each instruction that [`Cpu::step`] executes.
+    #[cfg(feature = "std")]
+    pub fn set_trace_callback(&mut self, callback: TraceCallback) {
+        self.tracer = Tracer(Some(callback));
+    }
+
+    /// Writes the disassembly of `range` to the file at `path`, one
+    /// `ADDR  INSTRUCTION` line per instruction, decoded for [`Cpu::variant`], see
+    /// [`Variant::disassemble_range`].
+    #[cfg(feature = "std")]
+    pub fn dump_disassembly(
+        &self,
+        range: core::ops::RangeInclusive<Word>,
+        path: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<()> {
+        let listing: String = self
+            .variant
+            .disassemble_range(&self.memory, range, None, &[])
+            .into_iter()
+            .map(|(address, text)| format!("{:04X}  {}\n", address, text))
+            .collect();
+        std::fs::write(path, listing)
+    }
+
+    /// Writes a save state of the registers and all of memory to the file at `path`,
+    /// see [`crate::state`].
+    #[cfg(feature = "std")]
+    pub fn save_state(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.snapshot().to_state())
+    }
+
+    /// Restores a save state that was written by [`Cpu::save_state`], like
+    /// [`Cpu::restore`] does. A file that isn't a valid save state fails with
+    /// [`std::io::ErrorKind::InvalidData`] and a [`StateError`](crate::state::StateError).
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let bytes = std::fs::read(path)?;
+        let snapshot = CpuSnapshot::from_state(&bytes)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        self.restore(&snapshot);
+        Ok(())
+    }
+
+    /// Executes one instruction per record in `reference`, comparing the state of
+    /// the CPU before each instruction against the record. Execution stops at the
+    /// first divergence.
+    pub fn run_compared(&mut self, reference: &[TraceRecord]) -> Result<(), Divergence> {
+        for (index, expected) in reference.iter().enumerate() {
+            let actual = self.trace_record();
+            if let Some((field, expected, actual)) = expected.first_difference(&actual) {
+                return Err(Divergence {
+                    index,
+                    field,
+                    expected,
+                    actual,
+                });
+            }
+            self.execute_next_instruction();
+        }
+        Ok(())
+    }
+
+    /// Advances the CPU by exactly one bus cycle and returns the access that was
+    /// performed on the bus during that cycle.
+    ///
+    /// Only some instructions are stepped cycle by cycle (see [`Cpu::is_cycle_stepped`]).
+    /// All other instructions are executed as a whole in the cycle that fetches their
+    /// opcode, which also adds all of their cycles to [`Cpu::cycles`]. Between
+    /// instructions, a pending IRQ is serviced like [`Cpu::step`] does, which is done
+    /// as a whole as well and starts with the discarded read of the next opcode.
+    ///
+    /// Fails like [`Cpu::step`], without executing anything.
+    pub fn tick(&mut self) -> Result<BusCycle, CpuError> {
+        self.undo = None;
+        let cycles = self.cycles;
+        let bus_cycle = match self.in_flight.take() {
+            Some(in_flight) => {
+                self.cycles += 1;
+                self.tick_in_flight(in_flight)
+            }
+            None => self.tick_instruction()?,
+        };
+        self.memory.tick_devices(self.cycles - cycles);
+        Ok(bus_cycle)
+    }
+
+    /// Services a pending IRQ, or starts the next instruction, for [`Cpu::tick`].
+    fn tick_instruction(&mut self) -> Result<BusCycle, CpuError> {
+        let address = self.pc;
+        let irq_pending = self.irq_pending();
+        self.polled_interrupt_disable = None;
+        if irq_pending {
+            let opcode = self.memory.read(address);
+            self.interrupt(IRQ_VECTOR);
+            return Ok(BusCycle::read(address, opcode));
+        }
+
+        let opcode = self.memory.peek(address);
+        let info = match self.decode(opcode) {
+            Some(info) => info,
+            None if self.on_invalid == InvalidOpcodePolicy::Panic => self.invalid_opcode(address),
+            None => return Err(CpuError::InvalidOpcode { address, opcode }),
+        };
+        self.last_instruction = self.variant.decode_at(&self.memory, address);
+        self.fetch_and_advance_pc();
+        if Self::is_cycle_stepped(info.instruction()) {
+            self.cycles += 1;
+            self.in_flight = Some(InFlight {
+                instruction: info.instruction(),
+                cycle: 1,
+                address: 0,
+            });
+        } else {
+            self.begin_undo();
+            let result = self.execute_with_delayed_poll(info, address);
+            if result.is_err() {
+                self.undo_last();
+            }
+            self.undo = None;
+            result?;
+        }
+        Ok(BusCycle::read(address, opcode))
+    }
+
+    /// Advances the CPU by one clock cycle and returns whether an instruction has
+    /// been completed with it, for hosts that drive the CPU from an external clock.
+    ///
+    /// This is built on [`Cpu::tick`], so instructions that aren't cycle stepped are
+    /// executed in their first cycle and followed by idle cycles, which means that
+    /// [`Cpu::cycles`] is ahead of the clock until the instruction is completed.
+    /// Servicing an IRQ counts as an instruction.
+    pub fn clock(&mut self) -> Result<bool, CpuError> {
+        if self.clock_pending == 0 {
+            let cycles = self.cycles;
+            self.tick()?;
+            self.clock_pending = self.cycles - cycles;
+        }
+        self.clock_pending -= 1;
+        Ok(self.clock_pending == 0 && self.in_flight.is_none())
+    }
+
+    /// Finishes an instruction that was started with [`Cpu::tick`]. The remaining
+    /// cycles of an instruction in flight can't fail.
+    fn finish_in_flight(&mut self) {
+        while self.in_flight.is_some() {
+            self.tick()
+                .expect("the cycles of an instruction in flight can't fail");
+        }
+    }
+
+    /// Whether the given instruction is modelled cycle by cycle by [`Cpu::tick`].
+    pub fn is_cycle_stepped(instruction: Instruction) -> bool {
+        matches!(
+            (instruction.opcode, instruction.addressing_mode),
+            (Opcode::Lda, AddressingMode::Immediate) | (Opcode::Sta, AddressingMode::Absolute)
+        )
+    }
+
+    fn tick_in_flight(&mut self, mut in_flight: InFlight) -> BusCycle {
+        let instruction = in_flight.instruction;
+        let address = self.pc;
+        let cycle = match (
+            instruction.opcode,
+            instruction.addressing_mode,
+            in_flight.cycle,
+        ) {
+            (Opcode::Lda, AddressingMode::Immediate, 1) => {
+                let value = self.fetch_and_advance_pc();
+                self.set_zero_and_negative_flags(value);
+                self.a = value;
+                return BusCycle::read(address, value);
+            }
+            (Opcode::Sta, AddressingMode::Absolute, 1) => {
+                let low_byte = self.fetch_and_advance_pc();
+                in_flight.address = low_byte as Word;
+                BusCycle::read(address, low_byte)
+            }
+            (Opcode::Sta, AddressingMode::Absolute, 2) => {
+                let high_byte = self.fetch_and_advance_pc();
+                in_flight.address |= (high_byte as Word) << 8;
+                BusCycle::read(address, high_byte)
+            }
+            (Opcode::Sta, AddressingMode::Absolute, 3) => {
+                self.write(in_flight.address, self.a);
+                return BusCycle::write(in_flight.address, self.a);
+            }
+            _ => unreachable!(
+                "cycle {} of {:?} is not cycle stepped",
+                in_flight.cycle, instruction
+            ),
+        };
+
+        in_flight.cycle += 1;
+        self.in_flight = Some(in_flight);
+        cycle
+    }
+
+    /// Executes instructions until the CPU jams on an invalid opcode or, with
+    /// [`Cpu::detect_traps`], runs into a trap. The trapping instruction is the last
+    /// one that is yielded.
+    pub fn steps(&mut self) -> impl Iterator<Item = StepResult> + '_ {
+        let mut trapped = false;
+        core::iter::from_fn(move || {
+            if trapped {
+                return None;
+            }
+            let step = self.step().ok()?;
+            trapped = self.detect_traps && self.pc == step.pc;
+            Some(step)
+        })
+    }
+
+    fn execute_next_instruction(&mut self) -> StepResult {
+        match self.step() {
+            Ok(step) => step,
+            Err(CpuError::InvalidOpcode { address, .. }) => self.invalid_opcode(address),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Like [`Cpu::step`], but if the next instruction is a JSR or an IRQ is about
+    /// to be taken, runs until the subroutine or interrupt handler has returned to
+    /// the instruction after the call, or to the interrupted instruction. The result
+    /// describes the first instruction, with the cycles of all of them.
+    ///
+    /// The return is detected by the PC and the stack pointer being back where they
+    /// started, so this doesn't finish if the subroutine never returns.
+    pub fn step_over(&mut self) -> Result<StepResult, CpuError> {
+        self.finish_in_flight();
+        let sp = self.sp;
+        let return_address = if self.irq_pending() {
+            Some(self.pc)
         } else {
-            loop {
-                self.execute_next_instruction();
+            self.variant.opcodes()[self.memory.peek(self.pc) as usize]
+                .filter(|info| info.opcode == Opcode::Jsr)
+                .map(|info| self.pc.wrapping_add(info.bytes as Word))
+        };
+
+        let mut result = self.step()?;
+        if let Some(return_address) = return_address {
+            while self.pc != return_address || self.sp != sp {
+                result.cycles += self.step()?.cycles;
             }
         }
+        Ok(result)
     }
 
-    fn execute_next_instruction(&mut self) {
-        #[cfg(debug_assertions)]
-        let original_pc = self.pc;
+    /// Halts the CPU for the given number of cycles without executing anything, as
+    /// when a DMA transfer takes over the bus. The cycles are counted and attached
+    /// devices are ticked, but the registers and memory are left alone.
+    pub fn stall(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        self.memory.tick_devices(cycles);
+    }
 
-        let opcode = self.fetch_and_advance_pc();
-        let instruction = Instruction::try_from(opcode);
-        let instruction = match instruction {
-            Ok(instruction) => instruction,
-            Err(_) => {
-                self.invalid_opcode();
-                return;
+    /// Checks the invariants that the CPU keeps between instructions, and returns
+    /// all that are violated.
+    pub fn validate(&self) -> Result<(), Vec<InvariantViolation>> {
+        let mut violations = Vec::new();
+        if !self.status.contains(ProcessorStatus::_Unused) {
+            violations.push(InvariantViolation::UnusedFlagCleared);
+        }
+        if self.status.contains(ProcessorStatus::Break) {
+            violations.push(InvariantViolation::BreakFlagSet);
+        }
+        if let Some(in_flight) = &self.in_flight {
+            if !Self::is_cycle_stepped(in_flight.instruction) {
+                violations.push(InvariantViolation::NotCycleStepped(in_flight.instruction));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Whether an IRQ will be taken before the next instruction.
+    fn irq_pending(&self) -> bool {
+        let interrupt_disable = self
+            .polled_interrupt_disable
+            .unwrap_or(self.status.contains(ProcessorStatus::InterruptDisable));
+        (self.irq_line || self.memory.device_irq()) && !interrupt_disable
+    }
+
+    /// Executes the next instruction, after finishing one that was started with
+    /// [`Cpu::tick`] and servicing a pending IRQ.
+    ///
+    /// Fails without executing anything if the opcode is invalid. If an IRQ was
+    /// serviced before the invalid opcode in its handler, entering it is undone like
+    /// with [`Cpu::undo_last`]. Likewise, if the instruction fails with
+    /// [`CpuError::InternalDecode`], its effects are undone, so the CPU is left as it
+    /// was before the instruction.
+    pub fn step(&mut self) -> Result<StepResult, CpuError> {
+        // finish an instruction that was started with `tick` before starting a new one
+        self.finish_in_flight();
+        let cycles = self.cycles;
+        self.begin_undo();
+
+        let irq_pending = self.irq_pending();
+        self.polled_interrupt_disable = None;
+        if irq_pending {
+            self.interrupt(IRQ_VECTOR);
+        }
+
+        let pc = self.pc;
+        let opcode = self.memory.peek(pc);
+        let info = match self.decode(opcode) {
+            Some(info) => info,
+            None if self.on_invalid == InvalidOpcodePolicy::Panic => self.invalid_opcode(pc),
+            None => {
+                self.undo_last();
+                return Err(CpuError::InvalidOpcode {
+                    address: pc,
+                    opcode,
+                });
             }
         };
+        self.last_instruction = self.variant.decode_at(&self.memory, pc);
+        #[cfg(feature = "std")]
+        if self.tracer.0.is_some() {
+            let entry = TraceEntry {
+                record: self.trace_record(),
+                disassembly: self
+                    .last_instruction
+                    .map(|decoded| decoded.to_string())
+                    .unwrap_or_default(),
+            };
+            if let Some(callback) = &mut self.tracer.0 {
+                callback(&entry);
+            }
+        }
+        self.fetch_and_advance_pc();
+
+        if let Err(error) = self.execute_with_delayed_poll(info, pc) {
+            self.undo_last();
+            return Err(error);
+        }
 
-        let m = instruction.addressing_mode;
-        match instruction.opcode {
+        #[cfg(feature = "trace")]
+        {
+            println!("{}", self.trace_line(pc, opcode, info.opcode));
+        }
+
+        self.memory.tick_devices(self.cycles - cycles);
+        if self.debug_validate {
+            if let Err(violations) = self.validate() {
+                panic!("invalid CPU state after {:04X}: {:?}", pc, violations);
+            }
+        }
+        Ok(StepResult {
+            pc,
+            instruction: info.instruction(),
+            cycles: self.cycles - cycles,
+            interrupted: irq_pending,
+        })
+    }
+
+    /// Remembers the current state, which [`Cpu::undo_last`] restores.
+    fn begin_undo(&mut self) {
+        self.undo = Some(Undo {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.status,
+            cycles: self.cycles,
+            polled_interrupt_disable: self.polled_interrupt_disable,
+            writes: Vec::new(),
+        });
+    }
+
+    /// Executes the instruction like [`Cpu::execute`]. CLI, SEI and PLP change the
+    /// interrupt disable flag only after the interrupt poll, so the poll before the
+    /// next instruction still sees the previous flag.
+    fn execute_with_delayed_poll(&mut self, info: OpInfo, address: Word) -> Result<(), CpuError> {
+        let interrupt_disable = self.status.contains(ProcessorStatus::InterruptDisable);
+        self.execute(info, address)?;
+        if matches!(info.opcode, Opcode::Cli | Opcode::Sei | Opcode::Plp) {
+            self.polled_interrupt_disable = Some(interrupt_disable);
+        }
+        Ok(())
+    }
+
+    /// Looks up the opcode in the decode table, treating invalid opcodes as NOPs
+    /// with [`InvalidOpcodePolicy::NopSkip`].
+    fn decode(&self, opcode: Byte) -> Option<OpInfo> {
+        self.variant.opcodes()[opcode as usize]
+            .or((self.on_invalid == InvalidOpcodePolicy::NopSkip).then_some(NOP_SKIP))
+    }
+
+    /// Executes the instruction whose opcode at `address` has already been fetched.
+    fn execute(&mut self, info: OpInfo, address: Word) -> Result<(), CpuError> {
+        self.cycles += info.cycles as u64;
+
+        let m = info.addressing_mode;
+        match info.opcode {
             Opcode::Adc => self.execute_adc(m),
             Opcode::And => self.execute_and(m),
             Opcode::Asl => self.execute_asl(m),
@@ -124,7 +1156,7 @@ impl Cpu {
             Opcode::Ldx => self.execute_ldx(m),
             Opcode::Ldy => self.execute_ldy(m),
             Opcode::Lsr => self.execute_lsr(m),
-            Opcode::Nop => {}
+            Opcode::Nop => self.execute_nop(m),
             Opcode::Ora => self.execute_ora(m),
             Opcode::Pha => self.execute_pha(m),
             Opcode::Php => self.execute_php(m),
@@ -147,34 +1179,47 @@ impl Cpu {
             Opcode::Txa => self.execute_txa(m),
             Opcode::Txs => self.execute_txs(m),
             Opcode::Tya => self.execute_tya(m),
+            Opcode::Rmb(bit) => self.execute_rmb(bit, m),
+            Opcode::Smb(bit) => self.execute_smb(bit, m),
+            Opcode::Bbr(bit) => self.execute_bbr(bit, m),
+            Opcode::Bbs(bit) => self.execute_bbs(bit, m),
         };
 
-        #[cfg(feature = "trace")]
-        {
-            println!(
-                "{:04X} {:02X} {:?} |{:02X} {:02X} {:02X} {:02X}|{:08b}|",
-                original_pc,
-                opcode,
-                instruction.opcode,
-                self.a,
-                self.x,
-                self.y,
-                self.sp,
-                self.status.bits(),
-            );
+        if core::mem::take(&mut self.decode_failed) {
+            return Err(CpuError::InternalDecode {
+                address,
+                instruction: info.instruction(),
+            });
         }
+        Ok(())
     }
 
     fn execute_adc(&mut self, addressing_mode: AddressingMode) {
         let value = self.resolve_argument_value(addressing_mode);
-        let (new_value, carry) = self.a.overflowing_add(value);
-        self.status.set(ProcessorStatus::Carry, carry);
-        self.status.set(
-            ProcessorStatus::Overflow,
-            (self.a ^ new_value) & (value ^ new_value) & 0x80 > 0,
-        );
-        self.a = new_value;
-        self.set_zero_and_negative_flags(self.a);
+        let carry_in = self.status.contains(ProcessorStatus::Carry);
+        let a = self.a;
+        self.add_with_carry(value);
+
+        if self.decimal_mode() {
+            // the zero flag is still that of the binary result
+            let (a, value) = (a as Word, value as Word);
+            let mut low = (a & 0x0F) + (value & 0x0F) + carry_in as Word;
+            if low >= 0x0A {
+                low = ((low + 0x06) & 0x0F) + 0x10;
+            }
+            let mut sum = (a & 0xF0) + (value & 0xF0) + low;
+            // N and V are taken before the high nibble is adjusted
+            self.status.set(ProcessorStatus::Negative, sum & 0x80 > 0);
+            self.status.set(
+                ProcessorStatus::Overflow,
+                !(a ^ value) & (a ^ sum) & 0x80 > 0,
+            );
+            if sum >= 0xA0 {
+                sum += 0x60;
+            }
+            self.status.set(ProcessorStatus::Carry, sum >= 0x100);
+            self.a = sum as Byte;
+        }
     }
 
     fn execute_and(&mut self, addressing_mode: AddressingMode) {
@@ -184,15 +1229,9 @@ impl Cpu {
     }
 
     fn execute_asl(&mut self, addressing_mode: AddressingMode) {
-        let asl = |cpu: &mut Cpu, value: Byte| -> Byte {
-            cpu.status
-                .set(ProcessorStatus::Carry, value & 0b1000_0000 > 0);
-            let new_value = value << 1;
-            cpu.set_zero_and_negative_flags(new_value);
-            new_value
-        };
+        let asl = |cpu: &mut Cpu, value: Byte| cpu.shift(value, Shift::Left, false);
 
-        self.execute_on_acc_or_memory(addressing_mode, asl);
+        self.read_modify_write(addressing_mode, asl);
     }
 
     fn execute_bcc(&mut self, addressing_mode: AddressingMode) {
@@ -240,8 +1279,12 @@ impl Cpu {
         self.branch_if(|cpu| !cpu.status.contains(ProcessorStatus::Negative));
     }
 
-    fn execute_brk(&mut self, _: AddressingMode) {
-        panic!("BRK at {:#04X}", self.pc - 1);
+    fn execute_brk(&mut self, addressing_mode: AddressingMode) {
+        debug_assert_eq!(addressing_mode, AddressingMode::Implicit);
+
+        // BRK is followed by a signature byte, which is skipped on return
+        self.pc = self.pc.wrapping_add(1);
+        self.enter_interrupt(IRQ_VECTOR, self.status | ProcessorStatus::Break);
     }
 
     fn execute_bvc(&mut self, addressing_mode: AddressingMode) {
@@ -287,17 +1330,20 @@ impl Cpu {
     fn execute_compare(&mut self, addressing_mode: AddressingMode, v: Byte) {
         let value = self.resolve_argument_value(addressing_mode);
 
+        // the difference only sets the flags, the compared register is left unchanged
         let (new_value, carry) = v.overflowing_sub(value);
         self.status.set(ProcessorStatus::Carry, !carry);
         self.set_zero_and_negative_flags(new_value);
     }
 
     fn execute_dec(&mut self, addressing_mode: AddressingMode) {
-        let address = self.resolve_argument_address(addressing_mode);
-        let value = self.memory.read(address);
-        let new_value = value.wrapping_sub(1);
-        self.memory.write(address, new_value);
-        self.set_zero_and_negative_flags(new_value);
+        let dec = |cpu: &mut Cpu, value: Byte| {
+            let new_value = value.wrapping_sub(1);
+            cpu.set_zero_and_negative_flags(new_value);
+            new_value
+        };
+
+        self.read_modify_write(addressing_mode, dec);
     }
 
     fn execute_dex(&mut self, _: AddressingMode) {
@@ -317,11 +1363,13 @@ impl Cpu {
     }
 
     fn execute_inc(&mut self, addressing_mode: AddressingMode) {
-        let address = self.resolve_argument_address(addressing_mode);
-        let value = self.memory.read(address);
-        let new_value = value.wrapping_add(1);
-        self.memory.write(address, new_value);
-        self.set_zero_and_negative_flags(new_value);
+        let inc = |cpu: &mut Cpu, value: Byte| {
+            let new_value = value.wrapping_add(1);
+            cpu.set_zero_and_negative_flags(new_value);
+            new_value
+        };
+
+        self.read_modify_write(addressing_mode, inc);
     }
 
     fn execute_inx(&mut self, _: AddressingMode) {
@@ -342,10 +1390,9 @@ impl Cpu {
     fn execute_jsr(&mut self, addressing_mode: AddressingMode) {
         debug_assert_eq!(addressing_mode, AddressingMode::Absolute);
 
+        // the pushed return address is the last byte of the JSR, not the next instruction
         let address = self.resolve_argument_address(addressing_mode);
-        let return_address = self.pc - 1;
-        self.push((return_address >> 8) as Byte);
-        self.push((return_address & 0xFF) as Byte);
+        self.push_word(self.pc.wrapping_sub(1));
         self.pc = address;
     }
 
@@ -370,24 +1417,17 @@ impl Cpu {
     fn execute_lsr(&mut self, addressing_mode: AddressingMode) {
         assert_ne!(addressing_mode, AddressingMode::Immediate);
 
-        let lsr = |cpu: &mut Cpu, value: Byte| -> Byte {
-            cpu.status
-                .set(ProcessorStatus::Carry, value & 0b0000_0001 > 0);
-            let new_value = value >> 1;
-            cpu.set_zero_and_negative_flags(new_value);
-            new_value
-        };
+        let lsr = |cpu: &mut Cpu, value: Byte| cpu.shift(value, Shift::Right, false);
 
-        if addressing_mode == AddressingMode::Accumulator {
-            let value = self.a;
-            self.a = lsr(self, value);
-            return;
-        }
+        self.read_modify_write(addressing_mode, lsr);
+    }
 
-        let address = self.resolve_argument_address(addressing_mode);
-        let value = self.memory.read(address);
-        let new_value = lsr(self, value);
-        self.memory.write(address, new_value);
+    fn execute_nop(&mut self, addressing_mode: AddressingMode) {
+        // undocumented NOPs still fetch their operand and perform the read,
+        // the value is just discarded
+        if addressing_mode != AddressingMode::Implicit {
+            self.resolve_argument_value(addressing_mode);
+        }
     }
 
     fn execute_ora(&mut self, addressing_mode: AddressingMode) {
@@ -403,7 +1443,7 @@ impl Cpu {
 
     fn execute_php(&mut self, addressing_mode: AddressingMode) {
         debug_assert_eq!(addressing_mode, AddressingMode::Implicit);
-        self.push(self.status.bits());
+        self.push_status();
     }
 
     fn execute_pla(&mut self, addressing_mode: AddressingMode) {
@@ -414,75 +1454,67 @@ impl Cpu {
 
     fn execute_plp(&mut self, addressing_mode: AddressingMode) {
         debug_assert_eq!(addressing_mode, AddressingMode::Implicit);
-        self.status = ProcessorStatus::from_bits_truncate(self.pop());
+        self.pop_status();
     }
 
     fn execute_rol(&mut self, addressing_mode: AddressingMode) {
-        let rol = |cpu: &mut Cpu, value: Byte| -> Byte {
-            let mut new_value = value << 1;
-            if cpu.status.contains(ProcessorStatus::Carry) {
-                new_value |= 1;
-            }
-            cpu.set_zero_and_negative_flags(new_value);
-            cpu.a = new_value;
-            cpu.status
-                .set(ProcessorStatus::Carry, value & 0b1000_0000 > 0);
-            new_value
-        };
+        let rol = |cpu: &mut Cpu, value: Byte| cpu.shift(value, Shift::Left, true);
 
-        self.execute_on_acc_or_memory(addressing_mode, rol);
+        self.read_modify_write(addressing_mode, rol);
     }
 
     fn execute_ror(&mut self, addressing_mode: AddressingMode) {
-        let ror = |cpu: &mut Cpu, value: Byte| -> Byte {
-            let mut new_value = value >> 1;
-            if cpu.status.contains(ProcessorStatus::Carry) {
-                new_value |= 0b1000_0000;
-            }
-            cpu.set_zero_and_negative_flags(new_value);
-            cpu.a = new_value;
-            cpu.status
-                .set(ProcessorStatus::Carry, value & 0b0000_0001 > 0);
-            new_value
-        };
+        if !self.variant.has_ror() {
+            // the broken ROR shifts left, but leaves the carry alone
+            let broken_ror = |cpu: &mut Cpu, value: Byte| {
+                let new_value = value << 1;
+                cpu.set_zero_and_negative_flags(new_value);
+                new_value
+            };
+            self.read_modify_write(addressing_mode, broken_ror);
+            return;
+        }
+
+        let ror = |cpu: &mut Cpu, value: Byte| cpu.shift(value, Shift::Right, true);
 
-        self.execute_on_acc_or_memory(addressing_mode, ror);
+        self.read_modify_write(addressing_mode, ror);
     }
 
     fn execute_rti(&mut self, addressing_mode: AddressingMode) {
         debug_assert_eq!(addressing_mode, AddressingMode::Implicit);
 
-        self.status = ProcessorStatus::from_bits_truncate(self.pop());
-        let low_byte = self.pop();
-        let high_byte = self.pop();
-        self.pc = (high_byte as Word) << 8 | (low_byte as Word);
+        // unlike RTS, the popped address is the exact return address
+        self.pop_status();
+        self.pc = self.pop_word();
     }
 
     fn execute_rts(&mut self, addressing_mode: AddressingMode) {
         debug_assert_eq!(addressing_mode, AddressingMode::Implicit);
 
-        let low_byte = self.pop();
-        let high_byte = self.pop();
-        self.pc = (high_byte as Word) << 8 | (low_byte as Word);
-        self.pc += 1;
+        // JSR pushes the address of its last byte, so the return address is one past it
+        self.pc = self.pop_word().wrapping_add(1);
     }
 
     fn execute_sbc(&mut self, addressing_mode: AddressingMode) {
         let value = self.resolve_argument_value(addressing_mode);
-        let (new_value, carry) =
-            self.a
-                .overflowing_sub(if self.status.contains(ProcessorStatus::Carry) {
-                    value.wrapping_add(1)
-                } else {
-                    value
-                });
-        self.status.set(ProcessorStatus::Carry, !carry);
-        self.status.set(
-            ProcessorStatus::Overflow,
-            (self.a ^ new_value) & (!value ^ new_value) & 0x80 > 0,
-        );
-        self.a = new_value;
-        self.set_zero_and_negative_flags(self.a);
+        let borrow = !self.status.contains(ProcessorStatus::Carry);
+        let a = self.a;
+        // subtracting is adding the one's complement, with the carry as inverted borrow
+        self.add_with_carry(!value);
+
+        if self.decimal_mode() {
+            // all flags are those of the binary result, only A is adjusted
+            let (a, value, borrow) = (a as i16, value as i16, borrow as i16);
+            let low = (a & 0x0F) - (value & 0x0F) - borrow;
+            let mut result = a - value - borrow;
+            if result < 0 {
+                result -= 0x60;
+            }
+            if low < 0 {
+                result -= 0x06;
+            }
+            self.a = result as Byte;
+        }
     }
 
     fn execute_sec(&mut self, _: AddressingMode) {
@@ -499,17 +1531,17 @@ impl Cpu {
 
     fn execute_sta(&mut self, addressing_mode: AddressingMode) {
         let address = self.resolve_argument_address(addressing_mode);
-        self.memory.write(address, self.a);
+        self.write(address, self.a);
     }
 
     fn execute_stx(&mut self, addressing_mode: AddressingMode) {
         let address = self.resolve_argument_address(addressing_mode);
-        self.memory.write(address, self.x);
+        self.write(address, self.x);
     }
 
     fn execute_sty(&mut self, addressing_mode: AddressingMode) {
         let address = self.resolve_argument_address(addressing_mode);
-        self.memory.write(address, self.y);
+        self.write(address, self.y);
     }
 
     fn execute_tax(&mut self, _: AddressingMode) {
@@ -541,26 +1573,169 @@ impl Cpu {
         self.set_zero_and_negative_flags(self.a);
     }
 
+    fn execute_rmb(&mut self, bit: u8, addressing_mode: AddressingMode) {
+        let address = self.resolve_argument_address(addressing_mode);
+        let value = self.memory.read(address);
+        self.write(address, value & !(1 << bit));
+    }
+
+    fn execute_smb(&mut self, bit: u8, addressing_mode: AddressingMode) {
+        let address = self.resolve_argument_address(addressing_mode);
+        let value = self.memory.read(address);
+        self.write(address, value | 1 << bit);
+    }
+
+    fn execute_bbr(&mut self, bit: u8, addressing_mode: AddressingMode) {
+        debug_assert_eq!(addressing_mode, AddressingMode::ZeroPageRelative);
+
+        let value = self.read_zero_page_operand();
+        self.branch(value & 1 << bit == 0);
+    }
+
+    fn execute_bbs(&mut self, bit: u8, addressing_mode: AddressingMode) {
+        debug_assert_eq!(addressing_mode, AddressingMode::ZeroPageRelative);
+
+        let value = self.read_zero_page_operand();
+        self.branch(value & 1 << bit != 0);
+    }
+
+    /// Reads the zero page byte that the next operand byte points to.
+    fn read_zero_page_operand(&mut self) -> Byte {
+        let address = self.fetch_and_advance_pc() as Word;
+        self.memory.read(address)
+    }
+
+    /// Services a hardware interrupt, which takes the same 7 cycles as BRK.
+    fn interrupt(&mut self, vector: Word) {
+        // the break flag is only set in the pushed status for BRK and PHP
+        self.enter_interrupt(vector, self.status - ProcessorStatus::Break);
+        self.cycles += 7;
+    }
+
+    /// Pushes the PC and `status` and continues at the address stored in `vector`.
+    fn enter_interrupt(&mut self, vector: Word, status: ProcessorStatus) {
+        self.push_word(self.pc);
+        self.push((status | ProcessorStatus::_Unused).bits());
+        self.status.insert(ProcessorStatus::InterruptDisable);
+
+        let low_byte = self.memory.read(vector);
+        let high_byte = self.memory.read(vector + 1);
+        self.pc = (high_byte as Word) << 8 | (low_byte as Word);
+    }
+
+    /// Writes to memory and remembers the previous value for [`Cpu::undo_last`].
+    fn write(&mut self, address: Word, value: Byte) {
+        if let Some(undo) = &mut self.undo {
+            undo.writes.push((address, self.memory.peek(address)));
+        }
+        self.memory.write(address, value);
+    }
+
+    /// Formats a line of the trace that the `trace` feature prints after each
+    /// instruction. The SP is shown both as the raw byte and as the address in the
+    /// stack page that it points to.
+    #[cfg(any(test, feature = "trace"))]
+    fn trace_line(&self, pc: Word, opcode: Byte, instruction: Opcode) -> alloc::string::String {
+        alloc::format!(
+            "{:04X} {:02X} {:?} |{:02X} {:02X} {:02X} {:02X} {:04X}|{}|",
+            pc,
+            opcode,
+            instruction,
+            self.a,
+            self.x,
+            self.y,
+            self.sp,
+            self.stack_addr(),
+            self.status,
+        )
+    }
+
+    /// The address the SP points to. The SP only selects the byte within the stack
+    /// page, so the address never leaves the page, no matter how the SP wraps.
+    fn stack_addr(&self) -> Word {
+        STACK_START | self.sp as Word
+    }
+
     fn push(&mut self, byte: Byte) {
-        let address = STACK_START + self.sp as Word;
-        self.memory.write(address, byte);
-        self.sp = self.sp.checked_sub(1).expect("stack overflow");
+        self.write(self.stack_addr(), byte);
+        self.sp = self.sp.wrapping_sub(1);
     }
 
     fn pop(&mut self) -> Byte {
-        self.sp = self.sp.checked_add(1).expect("stack underflow");
-        let address = STACK_START + self.sp as Word;
-        self.memory.read(address)
+        self.sp = self.sp.wrapping_add(1);
+        self.memory.read(self.stack_addr())
+    }
+
+    /// Pushes the high byte first, so the word ends up little-endian in memory.
+    fn push_word(&mut self, word: Word) {
+        let [low, high] = word.to_le_bytes();
+        self.push(high);
+        self.push(low);
     }
 
+    fn pop_word(&mut self) -> Word {
+        let low = self.pop();
+        let high = self.pop();
+        Word::from_le_bytes([low, high])
+    }
+
+    // The unused bit is not an actual bit in the status register, it always reads as 1,
+    // so it's kept set in the live register as well as in the pushed copies. The break
+    // bit only exists in pushed copies and is dropped again when popping, or when the
+    // status is set from outside through `set_status`.
+
+    fn push_status(&mut self) {
+        // like BRK, PHP pushes the status with the break flag set
+        self.push((self.status | ProcessorStatus::Break | ProcessorStatus::_Unused).bits());
+    }
+
+    fn pop_status(&mut self) {
+        let status = ProcessorStatus::from_bits_truncate(self.pop());
+        self.set_status(status);
+    }
+
+    /// Branches if `f` returns true.
     fn branch_if(&mut self, f: fn(&mut Cpu) -> bool) {
+        let taken = f(self);
+        self.branch(taken);
+    }
+
+    /// Fetches the branch offset and applies it if `taken`. A taken branch takes an
+    /// additional cycle, and another one if the target is on a different page than
+    /// the next instruction.
+    fn branch(&mut self, taken: bool) {
         let value = self.fetch_and_advance_pc();
-        if f(self) {
-            self.pc = self.pc.wrapping_add_signed(value as i8 as i16);
+        if taken {
+            let target = self.pc.wrapping_add_signed(value as i8 as i16);
+            self.cycles += if target & 0xFF00 == self.pc & 0xFF00 {
+                1
+            } else {
+                2
+            };
+            self.pc = target;
         }
     }
 
-    fn execute_on_acc_or_memory(
+    /// Shifts `value` by one bit, which is what ASL, LSR, ROL and ROR do. The bit that
+    /// is shifted out goes into Carry. The bit that is shifted in is the old Carry if
+    /// `through_carry` is set (ROL, ROR) and zero otherwise (ASL, LSR).
+    fn shift(&mut self, value: Byte, direction: Shift, through_carry: bool) -> Byte {
+        let carry_in = through_carry && self.status.contains(ProcessorStatus::Carry);
+        let (new_value, carry_out) = match direction {
+            Shift::Left => (value << 1 | carry_in as Byte, value & 0b1000_0000 > 0),
+            Shift::Right => (
+                value >> 1 | (carry_in as Byte) << 7,
+                value & 0b0000_0001 > 0,
+            ),
+        };
+        self.status.set(ProcessorStatus::Carry, carry_out);
+        self.set_zero_and_negative_flags(new_value);
+        new_value
+    }
+
+    /// Replaces the accumulator or the byte in memory that `addressing_mode` refers
+    /// to with the result of `f`.
+    fn read_modify_write(
         &mut self,
         addressing_mode: AddressingMode,
         f: impl Fn(&mut Cpu, Byte) -> Byte,
@@ -573,68 +1748,46 @@ impl Cpu {
 
         let address = self.resolve_argument_address(addressing_mode);
         let value = self.memory.read(address);
+        // the NMOS 6502 writes the unmodified value back while it computes the new
+        // one, which devices can observe, while the 65C02 reads it again instead
+        if self.variant == Variant::Cmos65C02 {
+            self.memory.read(address);
+        } else {
+            self.write(address, value);
+        }
         let new_value = f(self, value);
-        self.memory.write(address, new_value);
+        self.write(address, new_value);
     }
 
     fn resolve_argument_address(&mut self, addressing_mode: AddressingMode) -> Word {
-        match addressing_mode {
-            AddressingMode::Accumulator | AddressingMode::Implicit | AddressingMode::Immediate => {
-                unreachable!(
-                    "{:?} addressing mode does not have an address",
-                    addressing_mode
-                );
-            }
-            AddressingMode::ZeroPage => self.fetch_and_advance_pc() as Word,
-            AddressingMode::ZeroPageX => {
-                let address = self.fetch_and_advance_pc();
-                address.wrapping_add(self.x) as Word
-            }
-            AddressingMode::ZeroPageY => {
-                let address = self.fetch_and_advance_pc();
-                address.wrapping_add(self.y) as Word
-            }
-            AddressingMode::Absolute => {
-                let low_byte = self.fetch_and_advance_pc();
-                let high_byte = self.fetch_and_advance_pc();
-                (high_byte as Word) << 8 | (low_byte as Word)
-            }
-            AddressingMode::AbsoluteX => {
-                let low_byte = self.fetch_and_advance_pc();
-                let high_byte = self.fetch_and_advance_pc();
-                let address = (high_byte as Word) << 8 | (low_byte as Word);
-                address.wrapping_add(self.x as Word)
-            }
-            AddressingMode::AbsoluteY => {
-                let low_byte = self.fetch_and_advance_pc();
-                let high_byte = self.fetch_and_advance_pc();
-                let address = (high_byte as Word) << 8 | (low_byte as Word);
-                address.wrapping_add(self.y as Word)
-            }
-            AddressingMode::Indirect => {
-                let low_byte = self.fetch_and_advance_pc();
-                let high_byte = self.fetch_and_advance_pc();
-                let address = (high_byte as Word) << 8 | (low_byte as Word);
-                let low_byte = self.memory.read(address);
-                let high_byte = self.memory.read(address + 1);
-                (high_byte as Word) << 8 | (low_byte as Word)
-            }
-            AddressingMode::IndexedIndirect => {
-                let address = self.fetch_and_advance_pc();
-                let address = address.wrapping_add(self.x) as Word;
-                let low_byte = self.memory.read(address);
-                let high_byte = self.memory.read(address + 1);
-                (high_byte as Word) << 8 | (low_byte as Word)
-            }
-            AddressingMode::IndirectIndexed => {
-                let address = self.fetch_and_advance_pc() as Word;
-                let low_byte = self.memory.read(address);
-                let high_byte = self.memory.read(address + 1);
-                let address = (high_byte as Word) << 8 | (low_byte as Word);
-                address.wrapping_add(self.y as Word)
-            }
-            _ => unimplemented!("addressing mode {:?} not implemented", addressing_mode),
+        self.resolve_argument_address_and_page_cross(addressing_mode)
+            .0
+    }
+
+    /// Resolves the address of the operand and whether indexing that address
+    /// crossed a page boundary.
+    fn resolve_argument_address_and_page_cross(
+        &mut self,
+        addressing_mode: AddressingMode,
+    ) -> (Word, bool) {
+        // these modes don't have an address, which is reported by `execute`
+        if let AddressingMode::Accumulator | AddressingMode::Implicit | AddressingMode::Immediate =
+            addressing_mode
+        {
+            self.decode_failed = true;
+            return (0, false);
         }
+
+        let memory = &mut self.memory;
+        let (address, pc, page_crossed) = resolve_operand(
+            |address| memory.read(address),
+            self.pc,
+            self.x,
+            self.y,
+            addressing_mode,
+        );
+        self.pc = pc;
+        (address, page_crossed)
     }
 
     fn resolve_argument_value(&mut self, addressing_mode: AddressingMode) -> Byte {
@@ -644,28 +1797,54 @@ impl Cpu {
             return self.a;
         }
 
-        let address = self.resolve_argument_address(addressing_mode);
+        // reads take an additional cycle if indexing crosses a page boundary
+        let (address, page_crossed) = self.resolve_argument_address_and_page_cross(addressing_mode);
+        if page_crossed {
+            self.cycles += 1;
+        }
         self.memory.read(address)
     }
 
+    /// Adds `value` and the carry to A in binary, setting C, V, N and Z.
+    fn add_with_carry(&mut self, value: Byte) {
+        let carry_in = self.status.contains(ProcessorStatus::Carry);
+        let sum = self.a as Word + value as Word + carry_in as Word;
+        let new_value = sum as Byte;
+        self.status.set(ProcessorStatus::Carry, sum > 0xFF);
+        self.status.set(
+            ProcessorStatus::Overflow,
+            (self.a ^ new_value) & (value ^ new_value) & 0x80 > 0,
+        );
+        self.a = new_value;
+        self.set_zero_and_negative_flags(self.a);
+    }
+
+    /// Whether ADC and SBC currently work on binary coded decimals.
+    fn decimal_mode(&self) -> bool {
+        self.variant.has_decimal_mode() && self.status.contains(ProcessorStatus::DecimalMode)
+    }
+
     fn set_zero_and_negative_flags(&mut self, value: Byte) {
         self.status.set(ProcessorStatus::Zero, value == 0);
         self.status
             .set(ProcessorStatus::Negative, value & 0b1000_0000 > 0);
     }
 
+    /// Reads the byte at the program counter and advances it by one.
+    ///
+    /// The program counter wraps from `0xFFFF` to `0x0000`, so an instruction at the
+    /// end of memory reads its remaining operand bytes from the start of memory.
     fn fetch_and_advance_pc(&mut self) -> Byte {
         let byte = self.memory.read(self.pc);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
         byte
     }
 
-    pub fn invalid_opcode(&mut self) {
-        let original_pc = self.pc - 1; // we've already advanced the pc by one, so we need to subtract one to get the original pc
+    fn invalid_opcode(&self, address: Word) -> ! {
         panic!(
             "Invalid opcode {:#02x}\npc: {:#02x}\nsp: {:#02x}\na: {:#02x}\nx: {:#02x}\ny: {:#02x}\nstatus: {:?}", 
-                self.memory.read(original_pc),
-                original_pc,
+                self.memory.peek(address),
+                address,
                 self.sp,
                 self.a,
                 self.x,
@@ -674,3 +1853,243 @@ impl Cpu {
         );
     }
 }
+
+/// Loads `program` at `start` into an otherwise empty memory and runs it from there
+/// until `limit` is reached or it traps (see [`Cpu::detect_traps`]). Returns the
+/// CPU in its final state.
+pub fn run_program(program: &[Byte], start: Word, limit: RunLimit) -> Cpu {
+    let mut memory = Memory::new();
+    memory.load(start, program);
+
+    let mut cpu = Cpu::new(memory);
+    cpu.pc = start;
+    cpu.detect_traps = true;
+    cpu.run(limit);
+    cpu
+}
+
+/// Computes the effective address of an operand that starts at `pc` (the address
+/// right after the opcode) and the PC after the operand, without any side effects.
+///
+/// For [`AddressingMode::Immediate`], the effective address is the address of the
+/// operand itself. [`AddressingMode::Implicit`] and [`AddressingMode::Accumulator`]
+/// don't have an operand, so the returned address is just `pc`.
+pub fn decode_operand(
+    memory: &Memory,
+    pc: Word,
+    x: Byte,
+    y: Byte,
+    addressing_mode: AddressingMode,
+) -> (Word, Word) {
+    let (address, pc, _) =
+        resolve_operand(|address| memory.peek(address), pc, x, y, addressing_mode);
+    (address, pc)
+}
+
+/// Like [`decode_operand`], but performs all reads through `read` and additionally
+/// returns whether indexing the address crossed a page boundary.
+fn resolve_operand(
+    mut read: impl FnMut(Word) -> Byte,
+    pc: Word,
+    x: Byte,
+    y: Byte,
+    addressing_mode: AddressingMode,
+) -> (Word, Word, bool) {
+    let next_pc = pc.wrapping_add(addressing_mode.operand_len() as Word);
+    let indexed = |address: Word, index: Byte| {
+        let indexed = address.wrapping_add(index as Word);
+        (indexed, next_pc, indexed & 0xFF00 != address & 0xFF00)
+    };
+    let unindexed = |address: Word| (address, next_pc, false);
+
+    let read_word = |read: &mut dyn FnMut(Word) -> Byte, low: Word, high: Word| {
+        Word::from_le_bytes([read(low), read(high)])
+    };
+    match addressing_mode {
+        AddressingMode::Implicit | AddressingMode::Accumulator | AddressingMode::Immediate => {
+            unindexed(pc)
+        }
+        AddressingMode::ZeroPage => unindexed(read(pc) as Word),
+        AddressingMode::ZeroPageX => unindexed(read(pc).wrapping_add(x) as Word),
+        AddressingMode::ZeroPageY => unindexed(read(pc).wrapping_add(y) as Word),
+        AddressingMode::ZeroPageRelative => unindexed(read(pc) as Word),
+        AddressingMode::Relative => {
+            let offset = read(pc) as i8;
+            unindexed(next_pc.wrapping_add_signed(offset as i16))
+        }
+        AddressingMode::Absolute => unindexed(read_word(&mut read, pc, pc.wrapping_add(1))),
+        AddressingMode::AbsoluteX => indexed(read_word(&mut read, pc, pc.wrapping_add(1)), x),
+        AddressingMode::AbsoluteY => indexed(read_word(&mut read, pc, pc.wrapping_add(1)), y),
+        AddressingMode::Indirect => {
+            let pointer = read_word(&mut read, pc, pc.wrapping_add(1));
+            unindexed(read_word(&mut read, pointer, pointer.wrapping_add(1)))
+        }
+        AddressingMode::IndexedIndirect => {
+            // the pointer always stays within the zero page
+            let pointer = read(pc).wrapping_add(x);
+            unindexed(read_word(
+                &mut read,
+                pointer as Word,
+                pointer.wrapping_add(1) as Word,
+            ))
+        }
+        AddressingMode::IndirectIndexed => {
+            let pointer = read(pc);
+            indexed(
+                read_word(&mut read, pointer as Word, pointer.wrapping_add(1) as Word),
+                y,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn test_push_pop_word() {
+        let mut cpu = Cpu::new(Memory::new());
+
+        cpu.push_word(0x1234);
+        assert_eq!(cpu.sp, 0xFD);
+        assert_eq!(cpu.memory[0x01FF], 0x12);
+        assert_eq!(cpu.memory[0x01FE], 0x34);
+
+        assert_eq!(cpu.pop_word(), 0x1234);
+        assert_eq!(cpu.sp, 0xFF);
+    }
+
+    #[test]
+    fn test_stack_wraps_within_page_one() {
+        let mut cpu = Cpu::new(Memory::new());
+        cpu.sp = 0x01;
+
+        for value in 0..=0xFF {
+            cpu.push(value);
+            assert!((STACK_START..=STACK_END).contains(&cpu.stack_addr()));
+        }
+        assert_eq!(cpu.sp, 0x01);
+        assert_eq!(cpu.memory[0x0101], 0x00);
+        assert_eq!(cpu.memory[0x0100], 0x01);
+        assert_eq!(
+            cpu.memory[0x01FF], 0x02,
+            "pushing wrapped from 0x0100 to 0x01FF"
+        );
+        assert_eq!(cpu.memory[0x0102], 0xFF);
+
+        cpu.sp = 0xFE;
+        cpu.memory[0x0100] = 0x42;
+        cpu.pop();
+        assert_eq!(cpu.pop(), 0x42, "popping wrapped from 0x01FF to 0x0100");
+        assert_eq!(cpu.stack_addr(), 0x0100);
+    }
+
+    #[test]
+    fn test_operand_wraps_past_end_of_memory() {
+        let mut cpu = Cpu::new(Memory::new());
+        cpu.memory[0xFFFE] = 0xAD; // LDA $1234
+        cpu.memory[0xFFFF] = 0x34;
+        cpu.memory[0x0000] = 0x12;
+        cpu.memory[0x1234] = 0x42;
+        cpu.set_pc(0xFFFE);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.a, 0x42);
+        assert_eq!(cpu.pc, 0x0001);
+    }
+
+    #[test]
+    fn test_trace_line_shows_sp_and_stack_address() {
+        let mut cpu = Cpu::new(Memory::new());
+        cpu.a = 0x12;
+        cpu.sp = 0xFD;
+        cpu.set_status(ProcessorStatus::Carry);
+
+        assert_eq!(
+            cpu.trace_line(0xC000, 0xEA, Opcode::Nop),
+            "C000 EA Nop |12 00 00 FD 01FD|-------C|"
+        );
+    }
+
+    #[test]
+    fn test_internal_decode_error() {
+        let mut cpu = Cpu::new(Memory::new());
+        let info = OpInfo {
+            opcode: Opcode::Sta,
+            addressing_mode: AddressingMode::Immediate,
+            bytes: 2,
+            cycles: 2,
+            undocumented: false,
+        };
+
+        assert_eq!(
+            cpu.execute(info, CODE_START),
+            Err(CpuError::InternalDecode {
+                address: CODE_START,
+                instruction: info.instruction(),
+            })
+        );
+        assert!(!cpu.decode_failed);
+    }
+
+    #[test]
+    fn test_set_status_keeps_unused_bit() {
+        let mut cpu = Cpu::new(Memory::new());
+
+        cpu.set_status(ProcessorStatus::Carry);
+        assert_eq!(
+            cpu.status,
+            ProcessorStatus::Carry | ProcessorStatus::_Unused
+        );
+        cpu.set_status(ProcessorStatus::empty());
+        assert_eq!(cpu.status, ProcessorStatus::_Unused);
+        cpu.set_status(ProcessorStatus::all());
+        assert!(!cpu.status.contains(ProcessorStatus::Break));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut cpu = Cpu::new(Memory::new());
+        assert_eq!(cpu.validate(), Ok(()));
+
+        cpu.status = ProcessorStatus::Break;
+        cpu.in_flight = Some(InFlight {
+            instruction: Instruction::new(Opcode::Nop, AddressingMode::Implicit),
+            cycle: 1,
+            address: 0,
+        });
+        assert_eq!(
+            cpu.validate(),
+            Err(vec![
+                InvariantViolation::UnusedFlagCleared,
+                InvariantViolation::BreakFlagSet,
+                InvariantViolation::NotCycleStepped(Instruction::new(
+                    Opcode::Nop,
+                    AddressingMode::Implicit
+                )),
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "UnusedFlagCleared")]
+    fn test_debug_validate() {
+        let mut cpu = Cpu::new(Memory::new());
+        cpu.memory[CODE_START as usize] = 0xEA; // NOP
+        cpu.debug_validate = true;
+        cpu.status.remove(ProcessorStatus::_Unused);
+        cpu.step().unwrap();
+    }
+
+    #[test]
+    fn test_status_display() {
+        let status = ProcessorStatus::Negative | ProcessorStatus::Carry;
+        assert_eq!(status.to_string(), "N------C");
+        assert_eq!((status | ProcessorStatus::_Unused).to_string(), "N------C");
+        assert_eq!(ProcessorStatus::all().to_string(), "NV-BDIZC");
+    }
+}
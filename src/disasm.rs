@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+
+use crate::cpu::Word;
+use crate::mem::Memory;
+pub use crate::opcode::{decode_at, DecodedInstruction};
+use crate::opcode::{AddressingMode, Variant};
+
+/// Maps addresses to label names, which are rendered instead of the raw address.
+pub type Symbols = HashMap<Word, String>;
+
+impl DecodedInstruction {
+    /// Renders the instruction, using the names in `symbols` for addresses that
+    /// have one.
+    pub fn text(&self, symbols: Option<&Symbols>) -> String {
+        let [byte, high] = self.operands;
+        let word = Word::from_le_bytes([byte, high]);
+        let target = |address: Word| match symbols.and_then(|symbols| symbols.get(&address)) {
+            Some(symbol) => symbol.clone(),
+            None => format!("${:04X}", address),
+        };
+
+        let operand = match self.instruction.addressing_mode {
+            AddressingMode::Implicit => String::new(),
+            AddressingMode::Accumulator => "A".to_string(),
+            AddressingMode::Immediate => format!("#${:02X}", byte),
+            AddressingMode::ZeroPage => format!("${:02X}", byte),
+            AddressingMode::ZeroPageX => format!("${:02X},X", byte),
+            AddressingMode::ZeroPageY => format!("${:02X},Y", byte),
+            AddressingMode::Relative => target(
+                self.addr
+                    .wrapping_add(2)
+                    .wrapping_add_signed(byte as i8 as i16),
+            ),
+            AddressingMode::Absolute => target(word),
+            AddressingMode::AbsoluteX => format!("{},X", target(word)),
+            AddressingMode::AbsoluteY => format!("{},Y", target(word)),
+            AddressingMode::Indirect => format!("({})", target(word)),
+            AddressingMode::IndexedIndirect => format!("(${:02X},X)", byte),
+            AddressingMode::IndirectIndexed => format!("(${:02X}),Y", byte),
+            AddressingMode::ZeroPageRelative => format!(
+                "${:02X},{}",
+                byte,
+                target(
+                    self.addr
+                        .wrapping_add(3)
+                        .wrapping_add_signed(high as i8 as i16)
+                )
+            ),
+        };
+
+        if operand.is_empty() {
+            self.instruction.opcode.to_string()
+        } else {
+            format!("{} {}", self.instruction.opcode, operand)
+        }
+    }
+}
+
+impl Display for DecodedInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text(None))
+    }
+}
+
+/// Disassembles the instruction at `address` for the default [`Variant`], see
+/// [`Variant::disassemble`].
+pub fn disassemble(memory: &Memory, address: Word, symbols: Option<&Symbols>) -> (String, usize) {
+    Variant::default().disassemble(memory, address, symbols)
+}
+
+/// The number of bytes that are rendered per `.byte` line within data ranges.
+const DATA_BYTES_PER_LINE: u32 = 8;
+
+/// Disassembles all instructions that start within `range` for the default
+/// [`Variant`], see [`Variant::disassemble_range`].
+pub fn disassemble_range(
+    memory: &Memory,
+    range: RangeInclusive<Word>,
+    symbols: Option<&Symbols>,
+    data_ranges: &[RangeInclusive<Word>],
+) -> Vec<(Word, String)> {
+    Variant::default().disassemble_range(memory, range, symbols, data_ranges)
+}
+
+impl Variant {
+    /// Disassembles the instruction at `address` and returns its text together
+    /// with its length in bytes.
+    ///
+    /// Bytes that don't decode to an instruction are rendered as `.byte $XX`.
+    /// Memory is accessed through [`Memory::peek`], so disassembling has no side
+    /// effects.
+    pub fn disassemble(
+        &self,
+        memory: &Memory,
+        address: Word,
+        symbols: Option<&Symbols>,
+    ) -> (String, usize) {
+        match self.decode_at(memory, address) {
+            Some(decoded) => (decoded.text(symbols), decoded.len),
+            None => (format!(".byte ${:02X}", memory.peek(address)), 1),
+        }
+    }
+
+    /// Disassembles all instructions that start within `range`, returning each
+    /// instruction's address and text.
+    ///
+    /// Bytes within `data_ranges` are rendered as `.byte` lines instead of being
+    /// decoded, as are instructions that would overlap a data range.
+    pub fn disassemble_range(
+        &self,
+        memory: &Memory,
+        range: RangeInclusive<Word>,
+        symbols: Option<&Symbols>,
+        data_ranges: &[RangeInclusive<Word>],
+    ) -> Vec<(Word, String)> {
+        let end = *range.end() as u32;
+        let is_data = |address: u32| {
+            data_ranges
+                .iter()
+                .any(|data| data.contains(&(address as Word)))
+        };
+
+        let mut lines = Vec::new();
+        let mut address = *range.start() as u32;
+        while address <= end {
+            if is_data(address) {
+                let len = (address..=end)
+                    .take(DATA_BYTES_PER_LINE as usize)
+                    .take_while(|&address| is_data(address))
+                    .count() as u32;
+                let bytes = (address..address + len)
+                    .map(|address| format!("${:02X}", memory.peek(address as Word)))
+                    .collect::<Vec<_>>();
+                lines.push((address as Word, format!(".byte {}", bytes.join(", "))));
+                address += len;
+                continue;
+            }
+
+            let (mut text, mut len) = self.disassemble(memory, address as Word, symbols);
+            if (address + 1..address + len as u32).any(is_data) {
+                text = format!(".byte ${:02X}", memory.peek(address as Word));
+                len = 1;
+            }
+            lines.push((address as Word, text));
+            address += len as u32;
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Byte;
+
+    fn memory_with(address: Word, bytes: &[Byte]) -> Memory {
+        let mut memory = Memory::new();
+        memory.load(address, bytes);
+        memory
+    }
+
+    #[test]
+    fn test_disassemble_modes() {
+        let memory = memory_with(
+            0xC000,
+            &[
+                0xA9, 0x11, // LDA #$11
+                0x9D, 0x34, 0x12, // STA $1234,X
+                0xB1, 0x20, // LDA ($20),Y
+                0x0A, // ASL A
+                0xD0, 0xF6, // BNE $C000
+                0x02, // invalid
+            ],
+        );
+
+        assert_eq!(
+            disassemble_range(&memory, 0xC000..=0xC00A, None, &[]),
+            vec![
+                (0xC000, "LDA #$11".to_string()),
+                (0xC002, "STA $1234,X".to_string()),
+                (0xC005, "LDA ($20),Y".to_string()),
+                (0xC007, "ASL A".to_string()),
+                (0xC008, "BNE $C000".to_string()),
+                (0xC00A, ".byte $02".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_data_ranges() {
+        let memory = memory_with(
+            0xC000,
+            &[
+                0xA9, 0x11, // LDA #$11
+                0x4C, 0x0E, 0xC0, // JMP $C00E
+                0xA9, 0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x00, 0x01, 0x02, // data
+                0x60, // RTS
+            ],
+        );
+
+        assert_eq!(
+            disassemble_range(&memory, 0xC000..=0xC00E, None, &[0xC005..=0xC00D]),
+            vec![
+                (0xC000, "LDA #$11".to_string()),
+                (0xC002, "JMP $C00E".to_string()),
+                (
+                    0xC005,
+                    ".byte $A9, $48, $65, $6C, $6C, $6F, $00, $01".to_string()
+                ),
+                (0xC00D, ".byte $02".to_string()),
+                (0xC00E, "RTS".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_symbols() {
+        let memory = memory_with(
+            0xC000,
+            &[
+                0x20, 0x00, 0xC1, // JSR print
+                0x20, 0x00, 0xC2, // JSR $C200
+                0xF0, 0xF8, // BEQ start
+            ],
+        );
+        let symbols = Symbols::from([(0xC000, "start".to_string()), (0xC100, "print".to_string())]);
+
+        assert_eq!(
+            disassemble(&memory, 0xC000, Some(&symbols)),
+            ("JSR print".to_string(), 3)
+        );
+        assert_eq!(
+            disassemble(&memory, 0xC003, Some(&symbols)),
+            ("JSR $C200".to_string(), 3)
+        );
+        assert_eq!(
+            disassemble(&memory, 0xC006, Some(&symbols)),
+            ("BEQ start".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn test_decode_at() {
+        let memory = memory_with(0x0400, &[0x9D, 0x34, 0x12]); // STA $1234,X
+
+        let decoded = decode_at(&memory, 0x0400).unwrap();
+        assert_eq!(decoded.operands, [0x34, 0x12]);
+        assert_eq!(decoded.len, 3);
+        assert_eq!(decoded.to_string(), "STA $1234,X");
+        assert_eq!(decode_at(&memory, 0x0403).unwrap().to_string(), "BRK");
+    }
+
+    #[test]
+    fn test_branch_length() {
+        use crate::opcode::{Instruction, Opcode};
+
+        assert_eq!(
+            Instruction::new(Opcode::Bne, AddressingMode::Relative).len(),
+            2
+        );
+
+        let memory = memory_with(
+            0xC000,
+            &[
+                0xD0, 0xFE, // BNE $C000
+                0x90, 0x02, // BCC $C006
+                0xEA, // NOP
+            ],
+        );
+        assert_eq!(
+            disassemble(&memory, 0xC000, None),
+            ("BNE $C000".to_string(), 2)
+        );
+        assert_eq!(
+            disassemble_range(&memory, 0xC000..=0xC004, None, &[]),
+            vec![
+                (0xC000, "BNE $C000".to_string()),
+                (0xC002, "BCC $C006".to_string()),
+                (0xC004, "NOP".to_string()),
+            ]
+        );
+    }
+}
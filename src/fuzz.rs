@@ -0,0 +1,122 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::cpu::{Byte, Cpu, CpuError, ProcessorStatus, StepResult, Word};
+use crate::mem::Memory;
+
+/// The state that [`fuzz_step`] starts from, built from arbitrary bytes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FuzzState {
+    pub pc: Word,
+    pub sp: Byte,
+    pub a: Byte,
+    pub x: Byte,
+    pub y: Byte,
+    pub status: Byte,
+    /// Loaded at address 0. Bytes beyond the 64KB address space are ignored.
+    pub memory: Vec<Byte>,
+}
+
+/// The outcome of [`fuzz_step`], with the state after the step. Its memory is as
+/// long as the initial one.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FuzzResult {
+    pub step: Result<StepResult, CpuError>,
+    pub state: FuzzState,
+}
+
+/// Executes a single step from `initial`. This is the target for fuzzers, so it must
+/// not panic for any input.
+///
+/// Output written to the output register is discarded.
+pub fn fuzz_step(initial: &FuzzState) -> FuzzResult {
+    let mut memory = Memory::new();
+    let len = initial.memory.len().min(0x10000);
+    memory.load(0, &initial.memory[..len]);
+    memory.set_output_sink(Box::new(|_| {}));
+
+    let mut cpu = Cpu::new(memory);
+    cpu.pc = initial.pc;
+    cpu.sp = initial.sp;
+    cpu.a = initial.a;
+    cpu.x = initial.x;
+    cpu.y = initial.y;
+    cpu.status = ProcessorStatus::from_bits_retain(initial.status);
+
+    let step = cpu.step();
+    FuzzResult {
+        step,
+        state: FuzzState {
+            pc: cpu.pc,
+            sp: cpu.sp,
+            a: cpu.a,
+            x: cpu.x,
+            y: cpu.y,
+            status: cpu.status.bits(),
+            memory: cpu.memory.slice(0..=Word::MAX)[..len].to_vec(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    /// A xorshift generator, which is good enough to pick random inputs.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn byte(&mut self) -> Byte {
+            self.next() as Byte
+        }
+    }
+
+    #[test]
+    fn test_fuzz_step_never_panics() {
+        let mut random = Random(0x6502_6502_6502_6502);
+        for _ in 0..5000 {
+            // the zero page, the stack and the code, with the rest of memory zeroed
+            let memory = (0..0x300).map(|_| random.byte()).collect::<Vec<_>>();
+            let pc = random.next() as Word % 0x300;
+            let state = FuzzState {
+                pc,
+                sp: random.byte(),
+                a: random.byte(),
+                x: random.byte(),
+                y: random.byte(),
+                status: random.byte(),
+                memory,
+            };
+            let result = fuzz_step(&state);
+            if let Err(CpuError::InvalidOpcode { address, .. }) = result.step {
+                assert_eq!(address, pc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fuzz_step_at_end_of_memory() {
+        let mut memory = vec![0; 0x10000];
+        memory[0xFFFF] = 0xEA; // NOP
+        let result = fuzz_step(&FuzzState {
+            pc: 0xFFFF,
+            sp: 0xFF,
+            a: 0,
+            x: 0,
+            y: 0,
+            status: 0,
+            memory,
+        });
+        assert!(result.step.is_ok());
+        assert_eq!(result.state.pc, 0x0000, "the PC wraps around");
+    }
+}
@@ -0,0 +1,197 @@
+//! Support for the [`asm6502!`](crate::asm6502) macro, which assembles a program at
+//! compile time.
+
+use crate::cpu::{Byte, Word};
+use crate::opcode::{AddressingMode, Opcode, OPCODES};
+
+/// Assembles instructions into a `[u8; N]` at compile time, e.g.
+/// `asm6502!(LDA #0x11; STA 0x10; BRK)`.
+///
+/// Only a limited grammar is supported, with the instructions separated by `;`:
+/// - `LDA #value` for immediate operands
+/// - `STA address` for zero page operands if the address fits into a byte and the
+///   instruction has a zero page form, and absolute operands otherwise
+/// - `INX` for implied operands, which includes the accumulator, as in `ASL`
+///
+/// Operands can be any constant expressions. Unknown mnemonics and unsupported
+/// addressing modes fail to compile.
+#[macro_export]
+macro_rules! asm6502 {
+    (@ [$($done:tt)*]) => {{
+        const INSTRUCTIONS: &[$crate::inline_asm::Instruction] = &[$($done)*];
+        const LEN: usize = $crate::inline_asm::assembled_len(INSTRUCTIONS);
+        const BYTES: [u8; LEN] = $crate::inline_asm::assemble::<LEN>(INSTRUCTIONS);
+        BYTES
+    }};
+    (@ [$($done:tt)*] $mnemonic:ident # $value:expr $(; $($rest:tt)*)?) => {
+        $crate::asm6502!(@ [$($done)* (
+            $crate::inline_asm::mnemonics::$mnemonic,
+            $crate::inline_asm::Operand::Immediate($value),
+        ),] $($($rest)*)?)
+    };
+    (@ [$($done:tt)*] $mnemonic:ident $(; $($rest:tt)*)?) => {
+        $crate::asm6502!(@ [$($done)* (
+            $crate::inline_asm::mnemonics::$mnemonic,
+            $crate::inline_asm::Operand::Implied,
+        ),] $($($rest)*)?)
+    };
+    (@ [$($done:tt)*] $mnemonic:ident $address:expr $(; $($rest:tt)*)?) => {
+        $crate::asm6502!(@ [$($done)* (
+            $crate::inline_asm::mnemonics::$mnemonic,
+            $crate::inline_asm::Operand::Address($address),
+        ),] $($($rest)*)?)
+    };
+    ($($source:tt)*) => {
+        $crate::asm6502!(@ [] $($source)*)
+    };
+}
+
+/// The operand of an instruction in [`asm6502!`](crate::asm6502).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Operand {
+    Implied,
+    Immediate(Word),
+    Address(Word),
+}
+
+pub type Instruction = (Opcode, Operand);
+
+macro_rules! mnemonics {
+    ($($mnemonic:ident => $opcode:ident),* $(,)?) => {
+        /// The opcodes by mnemonic, which is how [`asm6502!`](crate::asm6502)
+        /// resolves them.
+        pub mod mnemonics {
+            use crate::opcode::Opcode;
+
+            $(pub const $mnemonic: Opcode = Opcode::$opcode;)*
+        }
+
+        const fn same_opcode(a: Opcode, b: Opcode) -> bool {
+            matches!((a, b), $((Opcode::$opcode, Opcode::$opcode))|*)
+        }
+    };
+}
+
+mnemonics!(
+    ADC => Adc, AND => And, ASL => Asl, BCC => Bcc, BCS => Bcs, BEQ => Beq,
+    BIT => Bit, BMI => Bmi, BNE => Bne, BPL => Bpl, BRK => Brk, BVC => Bvc,
+    BVS => Bvs, CLC => Clc, CLD => Cld, CLI => Cli, CLV => Clv, CMP => Cmp,
+    CPX => Cpx, CPY => Cpy, DEC => Dec, DEX => Dex, DEY => Dey, EOR => Eor,
+    INC => Inc, INX => Inx, INY => Iny, JMP => Jmp, JSR => Jsr, LDA => Lda,
+    LDX => Ldx, LDY => Ldy, LSR => Lsr, NOP => Nop, ORA => Ora, PHA => Pha,
+    PHP => Php, PLA => Pla, PLP => Plp, ROL => Rol, ROR => Ror, RTI => Rti,
+    RTS => Rts, SBC => Sbc, SEC => Sec, SED => Sed, SEI => Sei, STA => Sta,
+    STX => Stx, STY => Sty, TAX => Tax, TAY => Tay, TSX => Tsx, TXA => Txa,
+    TXS => Txs, TYA => Tya,
+);
+
+/// Returns the documented opcode byte of `opcode` with `addressing_mode`.
+const fn find(opcode: Opcode, addressing_mode: AddressingMode) -> Option<Byte> {
+    let mut byte = 0;
+    while byte < OPCODES.len() {
+        if let Some(info) = OPCODES[byte] {
+            if !info.undocumented
+                && same_opcode(info.opcode, opcode)
+                && info.addressing_mode as u8 == addressing_mode as u8
+            {
+                return Some(byte as Byte);
+            }
+        }
+        byte += 1;
+    }
+    None
+}
+
+/// Returns the opcode byte and the number of operand bytes of the instruction.
+const fn encode((opcode, operand): Instruction) -> (Byte, usize) {
+    let found = match operand {
+        Operand::Implied => match find(opcode, AddressingMode::Implicit) {
+            Some(byte) => Some((byte, 0)),
+            None => match find(opcode, AddressingMode::Accumulator) {
+                Some(byte) => Some((byte, 0)),
+                None => None,
+            },
+        },
+        Operand::Immediate(value) => {
+            assert!(value <= 0xFF, "immediate operand does not fit into a byte");
+            match find(opcode, AddressingMode::Immediate) {
+                Some(byte) => Some((byte, 1)),
+                None => None,
+            }
+        }
+        Operand::Address(address) => match find(opcode, AddressingMode::ZeroPage) {
+            Some(byte) if address <= 0xFF => Some((byte, 1)),
+            _ => match find(opcode, AddressingMode::Absolute) {
+                Some(byte) => Some((byte, 2)),
+                None => None,
+            },
+        },
+    };
+    match found {
+        Some(found) => found,
+        None => panic!("instruction does not support the addressing mode"),
+    }
+}
+
+#[doc(hidden)]
+pub const fn assembled_len(instructions: &[Instruction]) -> usize {
+    let mut len = 0;
+    let mut index = 0;
+    while index < instructions.len() {
+        len += 1 + encode(instructions[index]).1;
+        index += 1;
+    }
+    len
+}
+
+#[doc(hidden)]
+pub const fn assemble<const N: usize>(instructions: &[Instruction]) -> [Byte; N] {
+    let mut bytes = [0; N];
+    let mut len = 0;
+    let mut index = 0;
+    while index < instructions.len() {
+        let (opcode, operand_len) = encode(instructions[index]);
+        let [low, high] = match instructions[index].1 {
+            Operand::Implied => [0, 0],
+            Operand::Immediate(value) | Operand::Address(value) => value.to_le_bytes(),
+        };
+        bytes[len] = opcode;
+        if operand_len > 0 {
+            bytes[len + 1] = low;
+        }
+        if operand_len > 1 {
+            bytes[len + 2] = high;
+        }
+        len += 1 + operand_len;
+        index += 1;
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_addressing_modes() {
+        assert_eq!(
+            asm6502!(LDA #0x11; STA 0x10; STA 0x1234; JMP 0x0010; ASL; BRK),
+            [
+                0xA9, 0x11, // LDA #$11
+                0x85, 0x10, // STA $10
+                0x8D, 0x34, 0x12, // STA $1234
+                0x4C, 0x10, 0x00, // JMP $0010
+                0x0A, // ASL A
+                0x00, // BRK
+            ]
+        );
+        assert_eq!(asm6502!().len(), 0);
+    }
+
+    #[test]
+    fn test_constant_operands() {
+        const TARGET: u16 = 0xC000;
+        assert_eq!(
+            asm6502!(JSR TARGET; LDX #1 + 2),
+            [0x20, 0x00, 0xC0, 0xA2, 0x03]
+        );
+    }
+}
@@ -1,31 +1,137 @@
+//! A MOS 6502 emulator.
+//!
+//! With the default `std` feature disabled, the core (`cpu`, `mem`, `opcode`,
+//! `timer`, `trace`, `load`, `fuzz`, `inline_asm`, `analysis`, `state` and
+//! `util`) builds as `#![no_std]` and only needs `alloc`. The assembler and
+//! disassembler as well as all printing require `std`.
+//!
+//! The `single-step-tests` feature adds a runner for the SingleStepTests 65x02 JSON
+//! test vectors in `single_step`.
+//!
+//! The `send` feature requires devices and all callbacks to be `Send`, which makes
+//! `Cpu` `Send`, so that independent CPUs can run on separate threads.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod asm;
 pub mod cpu;
+#[cfg(feature = "std")]
+pub mod disasm;
+pub mod fuzz;
+pub mod inline_asm;
+pub mod load;
 pub mod mem;
 pub mod opcode;
+#[cfg(feature = "single-step-tests")]
+pub mod single_step;
+pub mod state;
+pub mod timer;
+pub mod trace;
+pub mod util;
 
 #[cfg(test)]
 mod tests {
-    use crate::cpu::{Cpu, ProcessorStatus, CODE_START};
-    use crate::mem::Memory;
+    use alloc::boxed::Box;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+    use alloc::{format, vec};
+    #[cfg(feature = "std")]
+    use std::sync::Mutex;
+
+    #[cfg(feature = "std")]
+    use crate::asm::assemble;
+    use crate::asm6502;
+    use crate::cpu::{
+        decode_operand, run_program, Cpu, CpuError, CpuSnapshot, InvalidOpcodePolicy,
+        ProcessorStatus, RunLimit, RunSummary, StopReason, Word, CODE_START, IRQ_VECTOR,
+        RESET_VECTOR,
+    };
+    use crate::mem::{BusCycle, Memory, RomWritePolicy};
+    use crate::opcode::{AddressingMode, Instruction, Opcode, Variant, OPCODES};
+    use crate::timer::TimerDevice;
+    #[cfg(feature = "std")]
+    use crate::trace::TraceEntry;
+    use crate::trace::{Divergence, TraceField, TraceRecord};
 
     fn run_code(code: &[u8], instruction_count: usize) -> Cpu {
+        let mut cpu = load_code(code);
+        cpu.run(RunLimit::Instructions(instruction_count));
+        cpu
+    }
+
+    fn load_code(code: &[u8]) -> Cpu {
         let mut mem = Memory::new();
 
         code.iter().enumerate().for_each(|(i, &b)| {
             mem[CODE_START as usize + i] = b;
         });
 
-        let mut cpu = Cpu::new(mem);
+        let cpu = Cpu::new(mem);
         assert_eq!(cpu.pc, CODE_START);
-        assert_eq!(cpu.sp, 0x0100);
+        assert_eq!(cpu.sp, 0xFF);
         assert_eq!(cpu.a, 0);
         assert_eq!(cpu.x, 0);
         assert_eq!(cpu.y, 0);
-        assert_eq!(cpu.status, ProcessorStatus::empty());
-
-        cpu.run(Some(instruction_count));
+        assert_eq!(cpu.status, ProcessorStatus::_Unused);
+        assert_eq!(cpu.cycles, 0);
         cpu
     }
 
+    #[cfg(feature = "std")]
+    /// Assembles the given program, which starts at [`CODE_START`] unless it has
+    /// an `.org` directive, and runs it like [`run_code`].
+    fn assemble_and_run(source: &str, instruction_count: usize) -> Cpu {
+        let program = assemble(source).unwrap();
+        assert_eq!(program.start, CODE_START);
+        run_code(&program.bytes, instruction_count)
+    }
+
+    /// A part of the CPU state that an instruction may change. The PC and the cycle
+    /// count aren't part of this, since every instruction changes them.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    enum StateField {
+        A,
+        X,
+        Y,
+        Sp,
+        Flag(ProcessorStatus),
+        Memory,
+    }
+
+    /// Fails if anything but the `allowed` fields differs between the two states.
+    fn assert_only_changed(before: &Cpu, after: &Cpu, allowed: &[StateField]) {
+        let registers = [
+            (StateField::A, before.a, after.a),
+            (StateField::X, before.x, after.x),
+            (StateField::Y, before.y, after.y),
+            (StateField::Sp, before.sp, after.sp),
+        ];
+        for (field, before, after) in registers {
+            if !allowed.contains(&field) {
+                assert_eq!(before, after, "{:?} changed", field);
+            }
+        }
+
+        for flag in ProcessorStatus::all().iter() {
+            if !allowed.contains(&StateField::Flag(flag)) {
+                assert_eq!(
+                    before.status.contains(flag),
+                    after.status.contains(flag),
+                    "{:?} changed",
+                    flag
+                );
+            }
+        }
+
+        if !allowed.contains(&StateField::Memory) {
+            assert_eq!(before.memory.diff(&after.memory), vec![], "memory changed");
+        }
+    }
+
     #[test]
     fn test_lda() {
         let state = run_code(
@@ -36,7 +142,16 @@ mod tests {
         );
         assert_eq!(state.pc, CODE_START + 2);
         assert_eq!(state.a, 0x11);
-        assert_eq!(state.status, ProcessorStatus::empty());
+        assert_eq!(state.status, ProcessorStatus::_Unused);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_lda_assembled() {
+        let state = assemble_and_run("LDA #$11", 1);
+        assert_eq!(state.pc, CODE_START + 2);
+        assert_eq!(state.a, 0x11);
+        assert_eq!(state.status, ProcessorStatus::_Unused);
     }
 
     #[test]
@@ -49,7 +164,10 @@ mod tests {
         );
         assert_eq!(state.pc, CODE_START + 2);
         assert_eq!(state.a, 0x0);
-        assert_eq!(state.status, ProcessorStatus::Zero);
+        assert_eq!(
+            state.status,
+            ProcessorStatus::Zero | ProcessorStatus::_Unused
+        );
     }
 
     #[test]
@@ -62,7 +180,10 @@ mod tests {
         );
         assert_eq!(state.pc, CODE_START + 2);
         assert_eq!(state.a, 0xFF);
-        assert_eq!(state.status, ProcessorStatus::Negative);
+        assert_eq!(
+            state.status,
+            ProcessorStatus::Negative | ProcessorStatus::_Unused
+        );
     }
 
     #[test]
@@ -88,4 +209,1838 @@ mod tests {
         assert_eq!(state.pc, CODE_START + 2);
         assert_eq!(state.y, 0x11);
     }
+
+    #[test]
+    fn test_tick_bus_cycles() {
+        let mut mem = Memory::new();
+        [
+            0xA9, 0x42, // LDA #0x42
+            0x8D, 0x34, 0x12, // STA 0x1234
+        ]
+        .into_iter()
+        .enumerate()
+        .for_each(|(i, b)| {
+            mem[CODE_START as usize + i] = b;
+        });
+
+        let mut cpu = Cpu::new(mem);
+        let cycles = (0..6).map(|_| cpu.tick().unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            cycles,
+            vec![
+                BusCycle::read(CODE_START, 0xA9),
+                BusCycle::read(CODE_START + 1, 0x42),
+                BusCycle::read(CODE_START + 2, 0x8D),
+                BusCycle::read(CODE_START + 3, 0x34),
+                BusCycle::read(CODE_START + 4, 0x12),
+                BusCycle::write(0x1234, 0x42),
+            ]
+        );
+        assert_eq!(cpu.pc, CODE_START + 5);
+        assert_eq!(cpu.a, 0x42);
+        assert_eq!(cpu.memory.read(0x1234), 0x42);
+    }
+
+    #[test]
+    fn test_nop_absolute() {
+        let state = run_code(
+            &[
+                0x0C, 0x34, 0x12, // NOP $1234 (undocumented)
+            ],
+            1,
+        );
+        assert_eq!(state.pc, CODE_START + 3);
+        assert_eq!(state.cycles, 4);
+    }
+
+    #[test]
+    fn test_nop_absolute_x_page_cross() {
+        let state = run_code(
+            &[
+                0xA2, 0x01, // LDX #0x01
+                0x1C, 0xFF, 0x12, // NOP $12FF,X (undocumented)
+            ],
+            2,
+        );
+        assert_eq!(state.pc, CODE_START + 5);
+        assert_eq!(state.cycles, 2 + 5);
+    }
+
+    #[test]
+    fn test_read_output_address() {
+        let mut cpu = load_code(&[
+            0x0C, 0x0F, 0x00, // NOP $000F (undocumented)
+            0xA5, 0x0F, // LDA $0F
+            0xA5, 0x0F, // LDA $0F
+        ]);
+
+        cpu.run(RunLimit::Instructions(2));
+        assert_eq!(cpu.a, 0x00);
+
+        cpu.memory.set_output_status(0x80);
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.a, 0x80);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_trap_detection() {
+        let program = assemble(
+            "
+                LDX #$05
+            loop:
+                DEX
+                BNE loop
+            here:
+                JMP here
+            ",
+        )
+        .unwrap();
+        let mut cpu = load_code(&program.bytes);
+        cpu.detect_traps = true;
+
+        assert_eq!(
+            cpu.run(RunLimit::Instructions(100)).reason,
+            StopReason::Trap(CODE_START + 5)
+        );
+        assert_eq!(cpu.x, 0);
+    }
+
+    #[test]
+    fn test_trap_detection_disabled() {
+        let mut cpu = load_code(&[
+            0x4C, 0x00, 0xC0, // JMP 0xC000
+        ]);
+        assert_eq!(
+            cpu.run(RunLimit::Instructions(10)).reason,
+            StopReason::InstructionLimit
+        );
+        assert_eq!(cpu.pc, CODE_START);
+    }
+
+    #[test]
+    fn test_run_compared() {
+        let code = [
+            0xA9, 0x11, // LDA #0x11
+            0xA2, 0x22, // LDX #0x22
+            0xA0, 0x33, // LDY #0x33
+        ];
+        let record = |pc, opcode, a, x, cycles| TraceRecord {
+            pc,
+            opcode,
+            a,
+            x,
+            y: 0,
+            sp: 0xFF,
+            status: ProcessorStatus::_Unused,
+            cycles,
+        };
+        let mut reference = vec![
+            record(CODE_START, 0xA9, 0x00, 0x00, 0),
+            record(CODE_START + 2, 0xA2, 0x11, 0x00, 2),
+            record(CODE_START + 4, 0xA0, 0x11, 0x22, 4),
+        ];
+
+        assert_eq!(load_code(&code).run_compared(&reference), Ok(()));
+
+        reference[2].x = 0x23;
+        let mut cpu = load_code(&code);
+        assert_eq!(
+            cpu.run_compared(&reference),
+            Err(Divergence {
+                index: 2,
+                field: TraceField::X,
+                expected: 0x23,
+                actual: 0x22,
+            })
+        );
+        assert_eq!(cpu.pc, CODE_START + 4, "stops at the divergence");
+    }
+
+    #[test]
+    fn test_break_flag_never_live() {
+        for pushed in [0x00, 0xFF] {
+            let mut cpu = load_code(&[
+                0xA9, pushed, // LDA #pushed
+                0x48,   // PHA
+                0x28,   // PLP
+                0x08,   // PHP
+            ]);
+            cpu.run(RunLimit::Instructions(3));
+            assert!(
+                !cpu.status.contains(ProcessorStatus::Break),
+                "after pulling {:#04X}",
+                pushed
+            );
+
+            cpu.run(RunLimit::Instructions(1));
+            assert_ne!(
+                cpu.memory[0x01FF] & ProcessorStatus::Break.bits(),
+                0,
+                "PHP pushes the break flag set"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unused_status_bit_always_set() {
+        let state = run_code(
+            &[
+                0xA9, 0x00, // LDA #0x00
+                0x48, // PHA
+                0x28, // PLP
+                0x08, // PHP
+            ],
+            4,
+        );
+        assert_eq!(state.status, ProcessorStatus::_Unused);
+        assert_eq!(
+            state.memory.peek(0x01FF),
+            (ProcessorStatus::Break | ProcessorStatus::_Unused).bits()
+        );
+
+        let state = run_code(
+            &[
+                0xA9, 0xC0, // LDA #0xC0
+                0x48, // PHA
+                0xA9, 0x06, // LDA #0x06
+                0x48, // PHA
+                0xA9, 0x01, // LDA #0x01
+                0x48, // PHA
+                0x40, // RTI
+            ],
+            7,
+        );
+        assert_eq!(state.pc, 0xC006);
+        assert_eq!(
+            state.status,
+            ProcessorStatus::Carry | ProcessorStatus::_Unused
+        );
+    }
+
+    #[test]
+    fn test_run_until_pc() {
+        let code = [
+            0xA9, 0x11, // LDA #0x11
+            0xA2, 0x22, // LDX #0x22
+            0xA0, 0x33, // LDY #0x33
+            0xEA, // NOP
+        ];
+
+        let mut cpu = load_code(&code);
+        assert_eq!(
+            cpu.run_until_pc(CODE_START + 6, 10).reason,
+            StopReason::PcReached(CODE_START + 6)
+        );
+        assert_eq!(cpu.pc, CODE_START + 6);
+        assert_eq!(cpu.y, 0x33);
+
+        let mut cpu = load_code(&code);
+        assert_eq!(
+            cpu.run_until_pc(0x1234, 2).reason,
+            StopReason::InstructionLimit
+        );
+        assert_eq!(cpu.pc, CODE_START + 4);
+    }
+
+    #[test]
+    fn test_run_summary_counts_partial_progress() {
+        let mut cpu = load_code(&[
+            0xA9, 0x11, // LDA #0x11
+            0x8D, 0x34, 0x12, // STA 0x1234
+            0xEA, // NOP
+            0xEA, // NOP
+        ]);
+        assert_eq!(
+            cpu.run_until_pc(CODE_START + 5, 10),
+            RunSummary {
+                reason: StopReason::PcReached(CODE_START + 5),
+                instructions: 2,
+                cycles: 2 + 4,
+            }
+        );
+        assert_eq!(
+            cpu.run(RunLimit::Instructions(2)),
+            RunSummary {
+                reason: StopReason::InstructionLimit,
+                instructions: 2,
+                cycles: 4,
+            },
+            "counted for this call only"
+        );
+    }
+
+    #[test]
+    fn test_run_stops_on_error() {
+        let mut cpu = load_code(&[
+            0xA9, 0x11, // LDA #0x11
+            0xEA, // NOP
+            0x02, // invalid
+        ]);
+        assert_eq!(
+            cpu.run(RunLimit::Instructions(10)),
+            RunSummary {
+                reason: StopReason::Error(CpuError::InvalidOpcode {
+                    address: CODE_START + 3,
+                    opcode: 0x02,
+                }),
+                instructions: 2,
+                cycles: 2 + 2,
+            }
+        );
+        assert_eq!(cpu.pc, CODE_START + 3);
+        assert_eq!(cpu.a, 0x11);
+    }
+
+    #[test]
+    fn test_asl_shifts_in_zero() {
+        let state = run_code(
+            &[
+                0x38, // SEC
+                0xA9, 0x81, // LDA #0x81
+                0x0A, // ASL A
+            ],
+            3,
+        );
+        assert_eq!(state.a, 0x02);
+        assert!(state.status.contains(ProcessorStatus::Carry));
+    }
+
+    #[test]
+    fn test_rol_shifts_in_carry() {
+        let state = run_code(
+            &[
+                0x38, // SEC
+                0xA9, 0x81, // LDA #0x81
+                0x2A, // ROL A
+            ],
+            3,
+        );
+        assert_eq!(state.a, 0x03);
+        assert!(state.status.contains(ProcessorStatus::Carry));
+    }
+
+    #[test]
+    fn test_lsr_and_ror() {
+        let code = asm6502!(SEC; LDA #0x81; STA 0x10; LSR; ROR 0x10);
+        assert_eq!(
+            code,
+            [
+                0x38, // SEC
+                0xA9, 0x81, // LDA #0x81
+                0x85, 0x10, // STA 0x10
+                0x4A, // LSR A
+                0x66, 0x10, // ROR 0x10
+            ]
+        );
+
+        let state = run_code(&code, 5);
+        assert_eq!(state.a, 0x40);
+        assert_eq!(state.memory.peek(0x10), 0xC0);
+        assert!(state.status.contains(ProcessorStatus::Carry));
+        assert!(state.status.contains(ProcessorStatus::Negative));
+    }
+
+    #[test]
+    fn test_shifts_on_accumulator_and_memory() {
+        for (accumulator, zero_page, expected) in [
+            (0x0A, 0x06, 0x02), // ASL
+            (0x2A, 0x26, 0x03), // ROL
+            (0x4A, 0x46, 0x40), // LSR
+            (0x6A, 0x66, 0xC0), // ROR
+        ] {
+            let state = run_code(
+                &[
+                    0x38, // SEC
+                    0xA9,
+                    0x81, // LDA #0x81
+                    accumulator,
+                ],
+                3,
+            );
+            assert_eq!(state.a, expected, "{:#04X}", accumulator);
+            assert!(state.status.contains(ProcessorStatus::Carry));
+
+            let state = run_code(
+                &[
+                    0x38, // SEC
+                    0xA9, 0x81, // LDA #0x81
+                    0x85, 0x10, // STA 0x10
+                    zero_page, 0x10,
+                ],
+                4,
+            );
+            assert_eq!(state.memory.peek(0x10), expected, "{:#04X}", zero_page);
+            assert_eq!(state.a, 0x81, "the accumulator is untouched");
+            assert!(state.status.contains(ProcessorStatus::Carry));
+        }
+    }
+
+    #[test]
+    fn test_irq_deferred_after_cli() {
+        let mut cpu = load_code(&[
+            0x58, // CLI
+            0xEA, // NOP
+            0xEA, // NOP
+        ]);
+        cpu.memory.load(IRQ_VECTOR, &[0x00, 0x80]);
+        cpu.memory.load(0x8000, &[0xA9, 0x42]); // LDA #0x42
+        cpu.status.insert(ProcessorStatus::InterruptDisable);
+        cpu.set_irq_line(true);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert!(!cpu.status.contains(ProcessorStatus::InterruptDisable));
+
+        // the instruction after CLI is still executed without taking the IRQ
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, CODE_START + 2);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, 0x8002);
+        assert_eq!(cpu.a, 0x42);
+        assert!(cpu.status.contains(ProcessorStatus::InterruptDisable));
+        assert_eq!(cpu.sp, 0xFC);
+        assert_eq!(cpu.memory.peek(0x01FF), 0xC0);
+        assert_eq!(cpu.memory.peek(0x01FE), 0x02);
+        assert_eq!(cpu.memory.peek(0x01FD), ProcessorStatus::_Unused.bits());
+    }
+
+    #[test]
+    fn test_effective_address() {
+        let mut cpu = load_code(&[
+            0x9D, 0x34, 0x12, // STA 0x1234,X
+            0xA9, 0x11, // LDA #0x11
+        ]);
+        cpu.x = 0x05;
+
+        assert_eq!(cpu.effective_address(), Some(0x1239));
+        assert_eq!(cpu.pc, CODE_START);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.effective_address(), None);
+    }
+
+    #[test]
+    fn test_decode_operand_matches_execution() {
+        let programs: [&[u8]; 7] = [
+            &[0xA5, 0x10],       // LDA 0x10
+            &[0xB5, 0xFE],       // LDA 0xFE,X
+            &[0xAD, 0x34, 0x12], // LDA 0x1234
+            &[0xB9, 0xFF, 0x12], // LDA 0x12FF,Y
+            &[0xA1, 0xFD],       // LDA (0xFD,X)
+            &[0xB1, 0xFF],       // LDA (0xFF),Y
+            &[0x6C, 0x20, 0x00], // JMP (0x0020)
+        ];
+
+        for code in programs {
+            let mut cpu = load_code(code);
+            cpu.x = 0x03;
+            cpu.y = 0x04;
+            cpu.memory.load(0x0000, &[0x00, 0x30]); // pointer at 0xFF wraps to 0x00
+            cpu.memory.load(0x0020, &[0x78, 0x56]);
+            cpu.memory.load(0x00FF, &[0x40]);
+
+            let mode = OPCODES[code[0] as usize].unwrap().addressing_mode;
+            let (address, next_pc) = decode_operand(&cpu.memory, CODE_START + 1, 0x03, 0x04, mode);
+            if code[0] == 0x6C {
+                cpu.run(RunLimit::Instructions(1));
+                assert_eq!(cpu.pc, address);
+                assert_eq!(next_pc, CODE_START + 3);
+                continue;
+            }
+
+            cpu.memory.write(address, 0x99);
+            cpu.run(RunLimit::Instructions(1));
+            assert_eq!(cpu.a, 0x99, "{:02X?} reads from {:#06X}", code, address);
+            assert_eq!(cpu.pc, next_pc, "{:02X?}", code);
+        }
+    }
+
+    #[test]
+    fn test_jsr_rts_round_trip() {
+        let mut cpu = load_code(&[
+            0x20, 0x10, 0xC0, // JSR 0xC010
+            0xA9, 0x01, // LDA #0x01
+        ]);
+        cpu.memory.load(0xC010, &[0x60]); // RTS
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, 0xC010);
+        assert_eq!(cpu.cycles, 6);
+        // the pushed return address is the last byte of the JSR
+        assert_eq!(cpu.memory[0x01FF], 0xC0);
+        assert_eq!(cpu.memory[0x01FE], 0x02);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, CODE_START + 3);
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(cpu.cycles, 12);
+    }
+
+    #[test]
+    fn test_brk_rti_round_trip() {
+        let mut cpu = load_code(&[
+            0x00, 0xEA, // BRK, followed by a signature byte
+            0xA9, 0x01, // LDA #0x01
+        ]);
+        cpu.memory.load(IRQ_VECTOR, &[0x10, 0xC0]);
+        cpu.memory.load(0xC010, &[0x40]); // RTI
+        cpu.status.insert(ProcessorStatus::Carry);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, 0xC010);
+        assert_eq!(cpu.cycles, 7);
+        assert!(cpu.status.contains(ProcessorStatus::InterruptDisable));
+        assert_eq!(cpu.memory[0x01FF], 0xC0);
+        assert_eq!(cpu.memory[0x01FE], 0x02);
+        assert_eq!(
+            cpu.memory[0x01FD],
+            (ProcessorStatus::Carry | ProcessorStatus::Break | ProcessorStatus::_Unused).bits()
+        );
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, CODE_START + 2);
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(
+            cpu.status,
+            ProcessorStatus::Carry | ProcessorStatus::_Unused
+        );
+        assert_eq!(cpu.cycles, 13);
+    }
+
+    #[test]
+    fn test_status_combinators() {
+        let status = ProcessorStatus::empty()
+            .with_carry(true)
+            .with_negative(true);
+        assert_eq!(status.bits(), 0b1000_0001);
+
+        let status = ProcessorStatus::all().with_zero(false).with_break(false);
+        assert_eq!(status.bits(), 0b1110_1101);
+    }
+
+    #[test]
+    fn test_next_instruction_cycles() {
+        let mut cpu = load_code(&[
+            0xBD, 0xFF, 0x12, // LDA 0x12FF,X
+            0x9D, 0xFF, 0x12, // STA 0x12FF,X
+            0xD0, 0x10, // BNE +0x10
+        ]);
+        cpu.x = 0x01;
+
+        assert_eq!(cpu.next_instruction_cycles(), 5);
+        assert_eq!(cpu.pc, CODE_START);
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.cycles, 5);
+
+        // stores always take the extra cycle, it's already part of the base cost
+        assert_eq!(cpu.next_instruction_cycles(), 5);
+        cpu.run(RunLimit::Instructions(1));
+
+        // branches are predicted as not taken
+        assert_eq!(cpu.next_instruction_cycles(), 2);
+    }
+
+    #[test]
+    fn test_vectors() {
+        let mut cpu = load_code(&[]);
+        cpu.memory
+            .load(0xFFFA, &[0x00, 0x90, 0x34, 0x12, 0xCD, 0xAB]);
+
+        assert_eq!(cpu.nmi_vector(), 0x9000);
+        assert_eq!(cpu.reset_vector(), 0x1234);
+        assert_eq!(cpu.irq_vector(), 0xABCD);
+    }
+
+    #[test]
+    fn test_cycle_limit() {
+        let mut cpu = load_code(&[
+            0xA9, 0x01, // LDA #0x01, 2 cycles
+            0xAD, 0x00, 0x02, // LDA 0x0200, 4 cycles
+            0xEA, // NOP, 2 cycles
+            0xEA, // NOP, 2 cycles
+            0xEA, // NOP, 2 cycles
+        ]);
+
+        assert_eq!(cpu.run(RunLimit::Cycles(10)).reason, StopReason::CycleLimit);
+        assert_eq!(cpu.pc, CODE_START + 7);
+        assert_eq!(cpu.cycles, 10);
+
+        // the limit is relative to where the run started, and the last instruction
+        // may overshoot it
+        assert_eq!(cpu.run(RunLimit::Cycles(1)).reason, StopReason::CycleLimit);
+        assert_eq!(cpu.pc, CODE_START + 8);
+        assert_eq!(cpu.cycles, 12);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_output_sink() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = load_code(&[
+            0xA9, 0x48, // LDA #'H'
+            0x85, 0x0F, // STA OUTPUT_ADDRESS
+            0xA9, 0x69, // LDA #'i'
+            0x85, 0x0F, // STA OUTPUT_ADDRESS
+        ]);
+        let sink = output.clone();
+        cpu.memory
+            .set_output_sink(Box::new(move |byte| sink.lock().unwrap().push(byte)));
+
+        cpu.run(RunLimit::Instructions(4));
+        assert_eq!(*output.lock().unwrap(), b"Hi");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_output_address() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = load_code(&[
+            0xA9, 0x48, // LDA #'H'
+            0x8D, 0x00, 0x60, // STA 0x6000
+            0x85, 0x0F, // STA 0x0F
+        ]);
+        let sink = output.clone();
+        cpu.memory
+            .set_output_sink(Box::new(move |byte| sink.lock().unwrap().push(byte)));
+        cpu.memory.set_output_address(0x6000);
+
+        cpu.run(RunLimit::Instructions(3));
+        assert_eq!(*output.lock().unwrap(), b"H");
+        assert_eq!(
+            cpu.memory.peek(0x6000),
+            0x00,
+            "handled by the output register"
+        );
+        assert_eq!(cpu.memory.peek(0x0F), 0x48, "plain RAM");
+    }
+
+    #[test]
+    fn test_undo_last() {
+        let mut cpu = load_code(&[
+            0xE6, 0x10, // INC 0x10
+            0x20, 0x00, 0xC1, // JSR 0xC100
+        ]);
+        cpu.memory[0x10] = 0x41;
+
+        assert!(!cpu.undo_last());
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.memory[0x10], 0x42);
+
+        assert!(cpu.undo_last());
+        assert_eq!(cpu.memory[0x10], 0x41);
+        assert_eq!(cpu.pc, CODE_START);
+        assert_eq!(cpu.cycles, 0);
+        assert!(!cpu.undo_last(), "only one instruction can be undone");
+
+        cpu.run(RunLimit::Instructions(2));
+        assert!(cpu.undo_last());
+        assert_eq!(cpu.pc, CODE_START + 2);
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(cpu.memory[0x01FF], 0x00);
+        assert_eq!(cpu.memory[0x01FE], 0x00);
+        assert_eq!(cpu.memory[0x10], 0x42);
+    }
+
+    #[test]
+    fn test_compare_keeps_register() {
+        // (operand, carry, zero, negative) for a register value of 0x40
+        let cases = [
+            (0x40, true, true, false),  // equal
+            (0x20, true, false, false), // register is greater
+            (0x50, false, false, true), // operand is greater
+        ];
+
+        for opcode in [0xC9, 0xE0, 0xC0] {
+            for (operand, carry, zero, negative) in cases {
+                let mut cpu = load_code(&[opcode, operand]);
+                cpu.a = 0x40;
+                cpu.x = 0x40;
+                cpu.y = 0x40;
+                cpu.run(RunLimit::Instructions(1));
+
+                let case = format!("{:02X} #{:02X}", opcode, operand);
+                assert_eq!((cpu.a, cpu.x, cpu.y), (0x40, 0x40, 0x40), "{}", case);
+                assert_eq!(
+                    cpu.status,
+                    ProcessorStatus::_Unused
+                        .with_carry(carry)
+                        .with_zero(zero)
+                        .with_negative(negative),
+                    "{}",
+                    case
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_steps() {
+        let mut cpu = load_code(&[
+            0xA2, 0x02, // LDX #0x02
+            0xCA, // DEX
+            0xD0, 0xFD, // BNE -3
+            0x02, // invalid
+        ]);
+
+        let opcodes = cpu
+            .steps()
+            .map(|step| (step.pc, step.instruction.opcode, step.cycles))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            opcodes,
+            vec![
+                (CODE_START, Opcode::Ldx, 2),
+                (CODE_START + 2, Opcode::Dex, 2),
+                (CODE_START + 3, Opcode::Bne, 3),
+                (CODE_START + 2, Opcode::Dex, 2),
+                (CODE_START + 3, Opcode::Bne, 2),
+            ]
+        );
+        assert_eq!(cpu.pc, CODE_START + 5, "the invalid opcode isn't executed");
+        assert_eq!(cpu.steps().take(3).count(), 0);
+    }
+
+    #[test]
+    fn test_sec_only_changes_carry() {
+        let mut cpu = load_code(&[0x38]); // SEC
+        cpu.a = 0x80;
+        let before = cpu.clone();
+        cpu.run(RunLimit::Instructions(1));
+
+        assert!(cpu.status.contains(ProcessorStatus::Carry));
+        assert_only_changed(&before, &cpu, &[StateField::Flag(ProcessorStatus::Carry)]);
+    }
+
+    #[test]
+    fn test_tax_only_changes_x_zero_and_negative() {
+        let mut cpu = load_code(&[0xAA]); // TAX
+        cpu.a = 0x80;
+        cpu.status
+            .insert(ProcessorStatus::Zero | ProcessorStatus::Carry);
+        let before = cpu.clone();
+        cpu.run(RunLimit::Instructions(1));
+
+        assert_eq!(cpu.x, 0x80);
+        assert_only_changed(
+            &before,
+            &cpu,
+            &[
+                StateField::X,
+                StateField::Flag(ProcessorStatus::Zero),
+                StateField::Flag(ProcessorStatus::Negative),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_run_program() {
+        let cpu = run_program(
+            &[
+                0xA9, 0x05, // LDA #0x05
+                0xAA, // TAX
+                0x4C, 0x03, 0x02, // JMP 0x0203
+            ],
+            0x0200,
+            RunLimit::Forever,
+        );
+
+        assert_eq!(cpu.a, 0x05);
+        assert_eq!(cpu.x, 0x05);
+        assert_eq!(cpu.pc, 0x0203);
+        assert_eq!(cpu.cycles, 2 + 2 + 3);
+    }
+
+    #[test]
+    fn test_branch_cycles() {
+        let mut cpu = load_code(&[0xD0, 0x10]); // BNE +0x10
+        cpu.status.insert(ProcessorStatus::Zero);
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, CODE_START + 2);
+        assert_eq!(cpu.cycles, 2, "not taken");
+
+        let mut cpu = load_code(&[0xD0, 0x10]); // BNE +0x10
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, CODE_START + 0x12);
+        assert_eq!(cpu.cycles, 3, "taken within the page");
+
+        let mut cpu = load_code(&[0xD0, 0xF0]); // BNE -0x10
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, CODE_START - 0x0E);
+        assert_eq!(cpu.cycles, 4, "taken across a page boundary");
+    }
+
+    #[test]
+    fn test_quick_snapshot() {
+        let mut cpu = load_code(&[
+            0xA9, 0x42, // LDA #0x42
+            0x85, 0x10, // STA 0x10
+            0x48, // PHA
+            0x8D, 0x00, 0x02, // STA 0x0200
+        ]);
+        let snapshot = cpu.quick_snapshot();
+
+        cpu.run(RunLimit::Instructions(4));
+        assert_eq!(cpu.memory[0x10], 0x42);
+        assert_eq!(cpu.memory[0x01FF], 0x42);
+
+        cpu.restore_quick(&snapshot);
+        assert_eq!(cpu.quick_snapshot(), snapshot);
+        assert_eq!(cpu.memory[0x10], 0x00);
+        assert_eq!(cpu.memory[0x01FF], 0x00);
+        assert_eq!((cpu.pc, cpu.sp, cpu.a), (CODE_START, 0xFF, 0x00));
+        assert_eq!(
+            cpu.memory[0x0200], 0x42,
+            "memory above the stack isn't restored"
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut cpu = load_code(&[
+            0xA9, 0x42, // LDA #0x42
+            0x48, // PHA
+            0x8D, 0x00, 0x80, // STA 0x8000
+            0xE8, // INX
+        ]);
+        cpu.run(RunLimit::Instructions(1));
+        let snapshot = cpu.snapshot();
+
+        cpu.run(RunLimit::Instructions(3));
+        assert_eq!(cpu.memory[0x8000], 0x42);
+        assert_ne!(cpu.snapshot(), snapshot);
+
+        cpu.restore(&snapshot);
+        assert_eq!(cpu.snapshot(), snapshot);
+        assert_eq!(cpu.memory[0x8000], 0x00);
+        assert_eq!(cpu.memory[0x01FF], 0x00);
+        assert_eq!(
+            (cpu.pc, cpu.sp, cpu.a, cpu.x),
+            (CODE_START + 2, 0xFF, 0x42, 0)
+        );
+        assert_eq!(cpu.cycles, 2);
+    }
+
+    #[test]
+    fn test_snapshot_memory_modes() {
+        fn assert_send<T: Send + Sync>(_: &T) {}
+
+        let mut cpu = load_code(&[
+            0xA9, 0x42, // LDA #0x42
+            0x8D, 0x00, 0x80, // STA 0x8000
+        ]);
+        let copy = cpu.snapshot();
+        let shared = cpu.snapshot_cow();
+        assert_send(&shared);
+        assert!(!Arc::ptr_eq(&copy.memory, &cpu.memory.ram()));
+        assert!(Arc::ptr_eq(&shared.memory, &cpu.memory.ram()));
+        assert_eq!(copy, shared);
+
+        cpu.run(RunLimit::Instructions(2));
+        assert!(!Arc::ptr_eq(&shared.memory, &cpu.memory.ram()));
+        assert_eq!(shared.memory[0x8000], 0x00, "the write copied the RAM");
+        assert_eq!(cpu.memory[0x8000], 0x42);
+
+        for snapshot in [copy, shared] {
+            let mut restored = cpu.clone();
+            restored.restore(&snapshot);
+            assert_eq!(restored.snapshot(), snapshot);
+            assert_eq!(restored.memory[0x8000], 0x00);
+        }
+    }
+
+    #[test]
+    fn test_rom_write_trap() {
+        let mut cpu = load_code(&[
+            0xA9, 0x42, // LDA #0x42
+            0x8D, 0x00, 0x90, // STA 0x9000
+            0x8D, 0x00, 0xA0, // STA 0xA000
+            0xEA, // NOP
+        ]);
+        cpu.memory
+            .set_read_only(0x8000..=0x9FFF, RomWritePolicy::Ignore);
+        cpu.memory
+            .set_read_only(0xA000..=0xBFFF, RomWritePolicy::Trap);
+
+        assert_eq!(
+            cpu.run(RunLimit::Instructions(4)).reason,
+            StopReason::RomWrite {
+                addr: 0xA000,
+                value: 0x42
+            }
+        );
+        assert_eq!(cpu.pc, CODE_START + 8);
+        assert_eq!(cpu.memory[0x9000], 0x00);
+        assert_eq!(cpu.memory[0xA000], 0x00);
+    }
+
+    /// Loads an instruction with the operand bytes `0x20 0x02` and X = Y = 4, and
+    /// returns the address it accesses.
+    fn load_with_operand(opcode: u8, addressing_mode: AddressingMode) -> (Cpu, Word) {
+        let mut cpu = load_code(&[opcode, 0x20, 0x02]);
+        cpu.x = 0x04;
+        cpu.y = 0x04;
+        let address = match addressing_mode {
+            AddressingMode::Immediate => CODE_START + 1,
+            AddressingMode::ZeroPage => 0x0020,
+            AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => 0x0024,
+            AddressingMode::Absolute => 0x0220,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => 0x0224,
+            AddressingMode::IndexedIndirect => {
+                cpu.memory.load(0x0024, &[0x00, 0x03]);
+                0x0300
+            }
+            AddressingMode::IndirectIndexed => {
+                cpu.memory.load(0x0020, &[0x00, 0x03]);
+                0x0304
+            }
+            mode => panic!("{:?} doesn't access memory", mode),
+        };
+        (cpu, address)
+    }
+
+    #[test]
+    fn test_load_flags_all_modes() {
+        let loads = OPCODES
+            .iter()
+            .enumerate()
+            .filter_map(|(opcode, info)| Some((opcode as u8, (*info)?)))
+            .filter(|(_, info)| matches!(info.opcode, Opcode::Lda | Opcode::Ldx | Opcode::Ldy));
+
+        for (opcode, info) in loads {
+            for (value, zero, negative) in [
+                (0x80, false, true),
+                (0x00, true, false),
+                (0x01, false, false),
+            ] {
+                let (mut cpu, address) = load_with_operand(opcode, info.addressing_mode);
+                cpu.memory[address as usize] = value;
+                cpu.status.insert(ProcessorStatus::Carry);
+                cpu.run(RunLimit::Instructions(1));
+
+                let case = format!(
+                    "{:?} {:?} {:#04X}",
+                    info.opcode, info.addressing_mode, value
+                );
+                let register = match info.opcode {
+                    Opcode::Lda => cpu.a,
+                    Opcode::Ldx => cpu.x,
+                    _ => cpu.y,
+                };
+                assert_eq!(register, value, "{}", case);
+                assert_eq!(
+                    cpu.status,
+                    ProcessorStatus::_Unused
+                        .with_carry(true)
+                        .with_zero(zero)
+                        .with_negative(negative),
+                    "{}",
+                    case
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_stores_keep_flags_all_modes() {
+        let stores = OPCODES
+            .iter()
+            .enumerate()
+            .filter_map(|(opcode, info)| Some((opcode as u8, (*info)?)))
+            .filter(|(_, info)| matches!(info.opcode, Opcode::Sta | Opcode::Stx | Opcode::Sty));
+
+        for (opcode, info) in stores {
+            let (mut cpu, address) = load_with_operand(opcode, info.addressing_mode);
+            // storing zero must neither set the zero flag nor clear the others
+            cpu.status
+                .insert(ProcessorStatus::Negative | ProcessorStatus::Carry);
+            cpu.memory[address as usize] = 0xFF;
+            let status = cpu.status;
+            match info.opcode {
+                Opcode::Sta => cpu.a = 0x00,
+                Opcode::Stx => cpu.x = 0x00,
+                _ => cpu.y = 0x00,
+            }
+            cpu.run(RunLimit::Instructions(1));
+
+            let case = format!("{:?} {:?}", info.opcode, info.addressing_mode);
+            assert_eq!(cpu.memory[address as usize], 0x00, "{}", case);
+            assert_eq!(cpu.status, status, "{}", case);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_access_log() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = load_code(&[0xAD, 0x34, 0x12]); // LDA 0x1234
+        cpu.memory[0x1234] = 0x42;
+        let accesses = log.clone();
+        cpu.memory
+            .set_access_log(Box::new(move |kind, address, data| {
+                accesses.lock().unwrap().push(BusCycle {
+                    address,
+                    data,
+                    kind,
+                })
+            }));
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                BusCycle::read(CODE_START, 0xAD),
+                BusCycle::read(CODE_START + 1, 0x34),
+                BusCycle::read(CODE_START + 2, 0x12),
+                BusCycle::read(0x1234, 0x42),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decimal_mode_only_affects_arithmetic() {
+        let code = [
+            0xA9, 0x99, // LDA #0x99
+            0x29, 0x1F, // AND #0x1F
+            0x09, 0x80, // ORA #0x80
+            0x49, 0x01, // EOR #0x01
+            0x0A, // ASL A
+            0xE6, 0x10, // INC 0x10
+            0xC6, 0x11, // DEC 0x11
+            0xC9, 0x30, // CMP #0x30
+        ];
+        let run = |decimal_mode: bool| {
+            let mut cpu = load_code(&code);
+            cpu.memory[0x10] = 0x09;
+            cpu.memory[0x11] = 0x10;
+            cpu.status.set(ProcessorStatus::DecimalMode, decimal_mode);
+            cpu.run(RunLimit::Instructions(8));
+            cpu.status.remove(ProcessorStatus::DecimalMode);
+            (cpu.a, cpu.memory[0x10], cpu.memory[0x11], cpu.status)
+        };
+
+        assert_eq!(run(true), run(false));
+        assert_eq!(run(false).0, 0x30);
+        assert_eq!((run(false).1, run(false).2), (0x0A, 0x0F));
+    }
+
+    #[test]
+    fn test_last_instruction() {
+        let mut cpu = load_code(&[
+            0xA9, 0x01, // LDA #0x01
+            0x8D, 0x34, 0x12, // STA 0x1234
+        ]);
+        assert_eq!(cpu.last_instruction(), None);
+
+        cpu.run(RunLimit::Instructions(2));
+        let last = cpu.last_instruction().unwrap();
+        assert_eq!(last.addr, CODE_START + 2);
+        assert_eq!(last.operands, [0x34, 0x12]);
+        assert_eq!(
+            last.instruction,
+            Instruction::new(Opcode::Sta, AddressingMode::Absolute)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_irq_entry_timing_and_bus_cycles() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = load_code(&[0xEA]); // NOP
+        cpu.memory.load(IRQ_VECTOR, &[0x00, 0x80]);
+        cpu.memory[0x8000] = 0xEA; // NOP
+        cpu.set_irq_line(true);
+        let accesses = log.clone();
+        cpu.memory
+            .set_access_log(Box::new(move |kind, address, data| {
+                accesses.lock().unwrap().push(BusCycle {
+                    address,
+                    data,
+                    kind,
+                })
+            }));
+
+        // 7 cycles to enter the interrupt, 2 for the NOP in the handler
+        let step = cpu.step().unwrap();
+        assert_eq!(step.pc, 0x8000);
+        assert_eq!(step.cycles, 7 + 2);
+        assert_eq!(
+            log.lock().unwrap()[..5],
+            [
+                BusCycle::write(0x01FF, 0xC0),
+                BusCycle::write(0x01FE, 0x00),
+                BusCycle::write(0x01FD, ProcessorStatus::_Unused.bits()),
+                BusCycle::read(IRQ_VECTOR, 0x00),
+                BusCycle::read(IRQ_VECTOR + 1, 0x80),
+            ]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_reset_timing_and_bus_cycles() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = load_code(&[0xEA]); // NOP
+        cpu.memory.load(RESET_VECTOR, &[0x00, 0x80]);
+        cpu.memory[0x01FF] = 0x42;
+        let accesses = log.clone();
+        cpu.memory
+            .set_access_log(Box::new(move |kind, address, data| {
+                accesses.lock().unwrap().push(BusCycle {
+                    address,
+                    data,
+                    kind,
+                })
+            }));
+
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x8000);
+        assert_eq!(cpu.sp, 0xFC);
+        assert_eq!(cpu.cycles, 7);
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                BusCycle::read(0x01FF, 0x42),
+                BusCycle::read(0x01FE, 0x00),
+                BusCycle::read(0x01FD, 0x00),
+                BusCycle::read(RESET_VECTOR, 0x00),
+                BusCycle::read(RESET_VECTOR + 1, 0x80),
+            ]
+        );
+        assert_eq!(cpu.memory[0x01FF], 0x42, "the stack isn't written");
+    }
+
+    #[test]
+    fn test_65c02_smb_and_bbr() {
+        let mut cpu = load_code(&[
+            0xB7, 0x10, // SMB3 0x10
+            0x7F, 0x10, 0x02, // BBR7 0x10,+2
+            0xEA, // NOP
+            0xEA, // NOP
+            0xFF, 0x10, 0x02, // BBS7 0x10,+2
+        ]);
+        cpu.variant = Variant::Cmos65C02;
+        cpu.memory[0x10] = 0x01;
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.memory[0x10], 0x09);
+        assert_eq!(cpu.cycles, 5);
+
+        // bit 7 is clear, so the NOPs are skipped
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, CODE_START + 7);
+        assert_eq!(cpu.cycles, 5 + 6);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.pc, CODE_START + 10, "BBS7 isn't taken");
+    }
+
+    #[test]
+    fn test_65c02_nops() {
+        let mut cpu = load_code(&[
+            0x03, // NOP
+            0x02, 0xFF, // NOP #$FF
+            0x5C, 0x34, 0x12, // NOP $1234
+        ]);
+        cpu.variant = Variant::Cmos65C02;
+
+        assert_eq!(cpu.run(RunLimit::Instructions(3)).cycles, 1 + 2 + 8);
+        assert_eq!(cpu.pc, CODE_START + 6);
+    }
+
+    #[test]
+    fn test_timer_device_irq() {
+        let mut cpu = load_code(&[
+            0xA9, 0x14, // LDA #20
+            0x8D, 0x00, 0xD0, // STA 0xD000
+            0xA9, 0x00, // LDA #0
+            0x8D, 0x01, 0xD0, // STA 0xD001
+            0x58, // CLI
+            0x4C, 0x0B, 0xC0, // JMP 0xC00B
+        ]);
+        cpu.memory.load(IRQ_VECTOR, &[0x00, 0x80]);
+        cpu.memory.load(0x8000, &[0x4C, 0x00, 0x80]); // JMP 0x8000
+        let timer = TimerDevice::new(0xD000);
+        cpu.memory
+            .attach_device(timer.range(), 0, "timer", Box::new(timer));
+
+        // devices are ticked after each instruction, so the countdown includes the
+        // cycles of the STA that starts it at cycle 8. It reaches zero at cycle 28,
+        // during the JMP that ends at cycle 29, and the IRQ is taken after that.
+        let entry = cpu.steps().find(|step| step.pc == 0x8000).unwrap();
+        assert_eq!(cpu.cycles - entry.cycles, 29);
+        assert_eq!(cpu.memory.read(0xD002), 0b1000_0001);
+    }
+
+    #[test]
+    fn test_timer_device_irq_with_clock() {
+        let mut cpu = load_code(&[
+            0xA9, 0x14, // LDA #20
+            0x8D, 0x00, 0xD0, // STA 0xD000
+            0xA9, 0x00, // LDA #0
+            0x8D, 0x01, 0xD0, // STA 0xD001
+            0x58, // CLI
+            0x4C, 0x0B, 0xC0, // JMP 0xC00B
+        ]);
+        cpu.memory.load(IRQ_VECTOR, &[0x00, 0x80]);
+        cpu.memory.load(0x8000, &[0x4C, 0x00, 0x80]); // JMP 0x8000
+        let timer = TimerDevice::new(0xD000);
+        cpu.memory
+            .attach_device(timer.range(), 0, "timer", Box::new(timer));
+
+        // the timer starts in the last cycle of the second STA at cycle 12 and
+        // reaches zero during the sixth JMP, which ends at cycle 32
+        let clocks = (1..100)
+            .find(|_| cpu.clock().unwrap() && cpu.pc == 0x8000)
+            .unwrap();
+        assert_eq!(clocks, 32 + 7);
+        assert_eq!(cpu.cycles, 32 + 7);
+        assert_eq!(cpu.memory.slice(0x01FE..=0x01FF), &[0x0B, 0xC0]);
+        assert!(cpu.status.contains(ProcessorStatus::InterruptDisable));
+    }
+
+    #[test]
+    fn test_tick_invalid_opcode() {
+        let mut cpu = load_code(&[0x02]); // invalid
+        assert_eq!(
+            cpu.tick(),
+            Err(CpuError::InvalidOpcode {
+                address: CODE_START,
+                opcode: 0x02,
+            })
+        );
+        assert_eq!((cpu.pc, cpu.cycles), (CODE_START, 0));
+    }
+
+    #[test]
+    fn test_step_over_jsr() {
+        let mut cpu = load_code(&[
+            0x20, 0x00, 0x80, // JSR 0x8000
+            0xEA, // NOP
+        ]);
+        cpu.memory.load(
+            0x8000,
+            &[
+                0xA2, 0x05, // LDX #5
+                0xCA, // DEX
+                0xD0, 0xFD, // BNE 0x8002
+                0x60, // RTS
+            ],
+        );
+
+        let step = cpu.step_over().unwrap();
+        assert_eq!(step.pc, CODE_START);
+        assert_eq!(step.instruction.opcode, Opcode::Jsr);
+        assert_eq!(step.cycles, cpu.cycles);
+        assert_eq!(cpu.pc, CODE_START + 3);
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(cpu.x, 0);
+
+        assert_eq!(
+            cpu.step_over().unwrap().pc,
+            CODE_START + 3,
+            "NOP is just stepped"
+        );
+        assert_eq!(cpu.pc, CODE_START + 4);
+    }
+
+    #[test]
+    fn test_brk_rti_skips_signature_byte() {
+        let mut cpu = load_code(&[
+            0x00, // BRK
+            0x42, // signature byte
+            0xA9, 0x01, // LDA #0x01
+        ]);
+        cpu.memory.load(IRQ_VECTOR, &[0x00, 0x80]);
+        cpu.memory[0x8000] = 0x40; // RTI
+
+        cpu.run(RunLimit::Instructions(2));
+        assert_eq!(cpu.pc, CODE_START + 2);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.a, 0x01);
+    }
+
+    #[test]
+    fn test_stall() {
+        let mut cpu = run_code(&[0xA9, 0x11], 1); // LDA #0x11
+        let before = cpu.clone();
+
+        cpu.stall(513);
+        assert_eq!(cpu.cycles, before.cycles + 513);
+        assert_eq!(cpu.pc, before.pc);
+        assert_only_changed(&before, &cpu, &[]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_trace_callback() {
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = load_code(&[
+            0xA9, 0x11, // LDA #0x11
+            0xAA, // TAX
+        ]);
+        let traced = entries.clone();
+        cpu.set_trace_callback(Box::new(move |entry| {
+            traced.lock().unwrap().push(entry.clone())
+        }));
+
+        cpu.run(RunLimit::Instructions(2));
+        let entries = entries.lock().unwrap();
+        assert_eq!(
+            entries[0],
+            TraceEntry {
+                record: TraceRecord {
+                    pc: CODE_START,
+                    opcode: 0xA9,
+                    a: 0x00,
+                    x: 0x00,
+                    y: 0x00,
+                    sp: 0xFF,
+                    status: ProcessorStatus::_Unused,
+                    cycles: 0,
+                },
+                disassembly: "LDA #$11".to_string(),
+            }
+        );
+        assert_eq!(entries[1].record.a, 0x11);
+        assert_eq!(entries[1].disassembly, "TAX");
+    }
+
+    #[test]
+    fn test_new_reset() {
+        let mut memory = Memory::new();
+        memory.load(RESET_VECTOR, &[0x00, 0x80]);
+
+        let cpu = Cpu::new_reset(memory.clone());
+        assert_eq!(cpu.pc, 0x8000);
+        assert_eq!(cpu.sp, 0xFD);
+        assert!(cpu.status.contains(ProcessorStatus::InterruptDisable));
+
+        assert_eq!(Cpu::new(memory).pc, CODE_START);
+    }
+
+    #[test]
+    fn test_zero_page_indexed_wraps() {
+        let mut cpu = load_code(&[
+            0xB5, 0xFF, // LDA 0xFF,X
+            0x95, 0xFE, // STA 0xFE,X
+            0xB6, 0xFF, // LDX 0xFF,Y
+            0x96, 0xF0, // STX 0xF0,Y
+        ]);
+        cpu.x = 0x02;
+        cpu.y = 0x12;
+        cpu.memory[0x0001] = 0x42;
+        cpu.memory[0x0101] = 0x99;
+        cpu.memory[0x0011] = 0x24;
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.a, 0x42, "read from 0x0001, not 0x0101");
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.memory[0x0000], 0x42);
+        assert_eq!(cpu.memory[0x0100], 0x00);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.x, 0x24, "read from 0x0011, not 0x0111");
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.memory[0x0002], 0x24);
+        assert_eq!(cpu.memory[0x0102], 0x00);
+    }
+
+    #[test]
+    fn test_adc_sbc_binary() {
+        let cpu = run_code(
+            &[
+                0x38, // SEC
+                0xA9, 0x50, // LDA #0x50
+                0x69, 0x2F, // ADC #0x2F
+            ],
+            3,
+        );
+        assert_eq!(cpu.a, 0x80);
+        assert!(cpu.status.contains(ProcessorStatus::Overflow));
+        assert!(!cpu.status.contains(ProcessorStatus::Carry));
+
+        let cpu = run_code(
+            &[
+                0x18, // CLC
+                0xA9, 0x00, // LDA #0x00
+                0xE9, 0x01, // SBC #0x01
+            ],
+            3,
+        );
+        assert_eq!(cpu.a, 0xFE, "the cleared carry borrows one");
+        assert!(!cpu.status.contains(ProcessorStatus::Carry));
+    }
+
+    #[test]
+    fn test_decimal_mode() {
+        let code = [
+            0xF8, // SED
+            0x18, // CLC
+            0xA9, 0x19, // LDA #0x19
+            0x69, 0x28, // ADC #0x28
+            0x85, 0x10, // STA 0x10
+            0x38, // SEC
+            0xA9, 0x42, // LDA #0x42
+            0xE9, 0x13, // SBC #0x13
+        ];
+
+        let mut cpu = run_code(&code, 5);
+        assert_eq!(cpu.memory[0x10], 0x47);
+        cpu.run(RunLimit::Instructions(3));
+        assert_eq!(cpu.a, 0x29);
+        assert!(cpu.status.contains(ProcessorStatus::Carry));
+
+        // the 2A03 ignores the decimal flag
+        let mut cpu = load_code(&code);
+        cpu.variant = Variant::Nes2A03;
+        cpu.run(RunLimit::Instructions(8));
+        assert_eq!(cpu.memory[0x10], 0x41);
+        assert_eq!(cpu.a, 0x2F);
+    }
+
+    #[test]
+    fn test_ror_without_ror() {
+        let code = [
+            0x38, // SEC
+            0xA9, 0xC1, // LDA #0xC1
+            0x85, 0x10, // STA 0x10
+            0x6A, // ROR A
+            0x66, 0x10, // ROR 0x10
+        ];
+
+        let cpu = run_code(&code, 5);
+        assert_eq!(cpu.a, 0xE0);
+        assert_eq!(cpu.memory[0x10], 0xE0);
+        assert!(cpu.status.contains(ProcessorStatus::Carry));
+
+        let mut cpu = load_code(&code);
+        cpu.variant = Variant::Nmos6502NoRor;
+        cpu.run(RunLimit::Instructions(5));
+        assert_eq!(cpu.a, 0x82);
+        assert_eq!(cpu.memory[0x10], 0x82);
+        assert!(
+            cpu.status.contains(ProcessorStatus::Carry),
+            "the carry is left alone"
+        );
+        assert!(cpu.status.contains(ProcessorStatus::Negative));
+    }
+
+    #[test]
+    fn test_16_bit_shift_left() {
+        let mut cpu = load_code(&[
+            0x06, 0x10, // ASL 0x10
+            0x26, 0x11, // ROL 0x11
+        ]);
+        cpu.memory.load(0x10, &0xC0A5u16.to_le_bytes());
+
+        cpu.run(RunLimit::Instructions(2));
+        let shifted = Word::from_le_bytes([cpu.memory[0x10], cpu.memory[0x11]]);
+        assert_eq!(shifted, 0xC0A5u16 << 1);
+        assert!(cpu.status.contains(ProcessorStatus::Carry), "top bit");
+        assert!(cpu.status.contains(ProcessorStatus::Negative));
+    }
+
+    #[test]
+    fn test_run_until_return() {
+        let mut cpu = load_code(&[
+            0x20, 0x00, 0x80, // JSR 0x8000
+            0xEA, // NOP
+        ]);
+        cpu.memory.load(
+            0x8000,
+            &[
+                0xA2, 0x03, // LDX #3
+                0x20, 0x00, 0x90, // JSR 0x9000
+                0xCA, // DEX
+                0xD0, 0xFA, // BNE 0x8002
+                0x60, // RTS
+            ],
+        );
+        cpu.memory.load(0x9000, &[0xC8, 0x60]); // INY; RTS
+
+        cpu.run(RunLimit::Instructions(2));
+        assert_eq!(cpu.pc, 0x8002);
+
+        assert_eq!(
+            cpu.run_until_return(100).reason,
+            StopReason::Returned(CODE_START + 3)
+        );
+        assert_eq!(cpu.pc, CODE_START + 3);
+        assert_eq!(cpu.y, 3, "nested calls were executed");
+        assert_eq!(cpu.sp, 0xFF);
+    }
+
+    #[test]
+    fn test_run_until_return_with_wrapping_stack() {
+        let mut cpu = load_code(&[
+            0x20, 0x00, 0x80, // JSR 0x8000
+            0xEA, // NOP
+        ]);
+        cpu.memory.load(0x8000, &[0x48, 0x68, 0x60]); // PHA; PLA; RTS
+        cpu.sp = 0x01;
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!((cpu.pc, cpu.sp), (0x8000, 0xFF));
+
+        assert_eq!(
+            cpu.run_until_return(10),
+            RunSummary {
+                reason: StopReason::Returned(CODE_START + 3),
+                instructions: 3,
+                cycles: 3 + 4 + 6,
+            }
+        );
+        assert_eq!(cpu.sp, 0x01);
+    }
+
+    #[test]
+    fn test_instruction_limit_with_interrupts() {
+        let run = |interrupts_count_as_instructions: bool| {
+            let mut cpu = load_code(&[0xEA]); // NOP
+            cpu.memory.load(IRQ_VECTOR, &[0x00, 0x80]);
+            cpu.memory.load(0x8000, &[0xE8; 8]); // INX
+            cpu.set_irq_line(true);
+            cpu.interrupts_count_as_instructions = interrupts_count_as_instructions;
+            cpu.run(RunLimit::Instructions(5));
+            cpu
+        };
+
+        let cpu = run(false);
+        assert_eq!(cpu.x, 5, "the interrupt doesn't use up an instruction");
+        assert_eq!(cpu.cycles, 7 + 5 * 2);
+        assert_eq!(run(true).x, 4);
+    }
+
+    #[test]
+    fn test_stores_have_no_page_cross_penalty() {
+        let cycles = |code: &[u8], x: u8| {
+            let mut cpu = load_code(code);
+            cpu.x = x;
+            cpu.run(RunLimit::Instructions(1));
+            cpu.cycles
+        };
+        let sta = [0x9D, 0xFF, 0x12]; // STA 0x12FF,X
+        let lda = [0xBD, 0xFF, 0x12]; // LDA 0x12FF,X
+
+        assert_eq!(cycles(&sta, 0), 5);
+        assert_eq!(cycles(&sta, 1), 5);
+        assert_eq!(cycles(&lda, 0), 4);
+        assert_eq!(cycles(&lda, 1), 5);
+    }
+
+    #[test]
+    fn test_invalid_opcode_nop_skip() {
+        let mut cpu = load_code(&[
+            0x02, // invalid
+            0xA9, 0x42, // LDA #0x42
+        ]);
+        assert!(cpu.clone().step().is_err());
+
+        cpu.on_invalid = InvalidOpcodePolicy::NopSkip;
+        let step = cpu.step().unwrap();
+        assert_eq!(step.instruction.opcode, Opcode::Nop);
+        assert_eq!(cpu.pc, CODE_START + 1);
+        assert_eq!(cpu.cycles, 2);
+
+        cpu.run(RunLimit::Instructions(1));
+        assert_eq!(cpu.a, 0x42);
+    }
+
+    #[test]
+    fn test_invalid_opcode_in_irq_handler() {
+        let mut cpu = load_code(&[0xEA]); // NOP
+        cpu.memory.load(IRQ_VECTOR, &[0x00, 0x80]);
+        cpu.memory[0x8000] = 0x02; // invalid
+        cpu.set_irq_line(true);
+        let before = cpu.snapshot();
+
+        assert_eq!(
+            cpu.step(),
+            Err(CpuError::InvalidOpcode {
+                address: 0x8000,
+                opcode: 0x02,
+            })
+        );
+        assert_eq!(cpu.snapshot(), before, "entering the IRQ is undone");
+        assert!(!cpu.undo_last());
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut cpu = load_code(&[
+            0xAD, 0x34, 0x12, // LDA 0x1234
+            0x8D, 0x00, 0x02, // STA 0x0200
+        ]);
+        cpu.memory[0x1234] = 0x42;
+        let clocks_until_done = |cpu: &mut Cpu| (1..).find(|_| cpu.clock().unwrap()).unwrap();
+
+        assert_eq!(clocks_until_done(&mut cpu), 4);
+        assert_eq!(cpu.a, 0x42);
+        assert_eq!(clocks_until_done(&mut cpu), 4, "cycle stepped");
+        assert_eq!(cpu.memory[0x0200], 0x42);
+        assert_eq!(cpu.cycles, 8);
+    }
+
+    #[test]
+    fn test_stack_instruction_flags() {
+        let mut cpu = load_code(&[
+            0x48, // PHA
+            0x68, // PLA
+            0x08, // PHP
+            0x28, // PLP
+        ]);
+        cpu.set_status(ProcessorStatus::Carry | ProcessorStatus::Negative);
+
+        let before = cpu.clone();
+        cpu.step().unwrap();
+        assert_only_changed(&before, &cpu, &[StateField::Sp, StateField::Memory]);
+
+        let before = cpu.clone();
+        cpu.step().unwrap();
+        assert_only_changed(
+            &before,
+            &cpu,
+            &[
+                StateField::A,
+                StateField::Sp,
+                StateField::Flag(ProcessorStatus::Zero),
+                StateField::Flag(ProcessorStatus::Negative),
+            ],
+        );
+        assert_eq!(
+            cpu.status,
+            ProcessorStatus::Carry | ProcessorStatus::Zero | ProcessorStatus::_Unused
+        );
+
+        let before = cpu.clone();
+        cpu.step().unwrap();
+        assert_only_changed(&before, &cpu, &[StateField::Sp, StateField::Memory]);
+        assert_eq!(
+            cpu.memory[0x01FF],
+            (ProcessorStatus::Carry
+                | ProcessorStatus::Zero
+                | ProcessorStatus::Break
+                | ProcessorStatus::_Unused)
+                .bits()
+        );
+
+        cpu.memory[0x01FF] =
+            (ProcessorStatus::Negative | ProcessorStatus::DecimalMode | ProcessorStatus::Break)
+                .bits();
+        cpu.step().unwrap();
+        assert_eq!(
+            cpu.status,
+            ProcessorStatus::Negative | ProcessorStatus::DecimalMode | ProcessorStatus::_Unused,
+            "PLP ignores the break flag and sets the unused bit"
+        );
+    }
+
+    #[test]
+    fn test_inc_dec_absolute_x_across_page() {
+        let code = [
+            0xA2, 0x10, // LDX #0x10
+            0xFE, 0xF0, 0x12, // INC 0x12F0,X
+        ];
+        for (value, expected, flags) in [
+            (0x7F, 0x80, ProcessorStatus::Negative),
+            (0xFF, 0x00, ProcessorStatus::Zero),
+            (0x00, 0x01, ProcessorStatus::empty()),
+        ] {
+            let mut cpu = load_code(&code);
+            cpu.memory[0x1300] = value;
+            cpu.run(RunLimit::Instructions(2));
+            assert_eq!(cpu.memory[0x1300], expected);
+            assert_eq!(
+                cpu.status & (ProcessorStatus::Negative | ProcessorStatus::Zero),
+                flags,
+                "INC {:#04X}",
+                value
+            );
+            assert_eq!(cpu.cycles, 2 + 7);
+        }
+
+        let code = [
+            0xA2, 0x01, // LDX #0x01
+            0xDE, 0xFF, 0x12, // DEC 0x12FF,X
+        ];
+        for (value, expected, flags) in [
+            (0x01, 0x00, ProcessorStatus::Zero),
+            (0x00, 0xFF, ProcessorStatus::Negative),
+            (0x80, 0x7F, ProcessorStatus::empty()),
+        ] {
+            let mut cpu = load_code(&code);
+            cpu.memory[0x1300] = value;
+            cpu.run(RunLimit::Instructions(2));
+            assert_eq!(cpu.memory[0x1300], expected);
+            assert_eq!(
+                cpu.status & (ProcessorStatus::Negative | ProcessorStatus::Zero),
+                flags,
+                "DEC {:#04X}",
+                value
+            );
+            assert_eq!(cpu.cycles, 2 + 7);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_dump_disassembly() {
+        let cpu = load_code(&[
+            0xA9, 0x42, // LDA #0x42
+            0x8D, 0x34, 0x12, // STA 0x1234
+            0x00, // BRK
+        ]);
+        let path = std::env::temp_dir().join(format!("disassembly-{}.txt", std::process::id()));
+
+        cpu.dump_disassembly(CODE_START..=CODE_START + 5, &path)
+            .unwrap();
+        let listing = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(listing, "C000  LDA #$42\nC002  STA $1234\nC005  BRK\n");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_dump_disassembly_65c02() {
+        let mut cpu = load_code(&[
+            0x07, 0x10, // RMB0 0x10
+            0x0F, 0x10, 0xFD, // BBR0 0x10, 0xC002
+        ]);
+        cpu.variant = Variant::Cmos65C02;
+        let path =
+            std::env::temp_dir().join(format!("disassembly-65c02-{}.txt", std::process::id()));
+
+        cpu.dump_disassembly(CODE_START..=CODE_START + 4, &path)
+            .unwrap();
+        let listing = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(listing, "C000  RMB0 $10\nC002  BBR0 $10,$C002\n");
+    }
+
+    #[test]
+    fn test_skip_instruction() {
+        let mut cpu = load_code(&[
+            0xA9, 0x42, // LDA #0x42
+            0x85, 0x10, // STA 0x10
+            0x02, // invalid
+        ]);
+        cpu.run(RunLimit::Instructions(1));
+        let before = cpu.snapshot();
+
+        assert_eq!(
+            cpu.skip_instruction(),
+            Some(Instruction::new(Opcode::Sta, AddressingMode::ZeroPage))
+        );
+        assert_eq!(cpu.pc, before.pc + 2);
+        assert_eq!(cpu.memory[0x10], 0x00);
+        assert_eq!(
+            CpuSnapshot {
+                pc: before.pc,
+                ..cpu.snapshot()
+            },
+            before,
+            "only the PC changed"
+        );
+
+        assert!(!cpu.undo_last(), "the LDA before the skip isn't undone");
+        assert_eq!(cpu.a, 0x42);
+
+        assert_eq!(cpu.skip_instruction(), None);
+        assert_eq!(cpu.pc, CODE_START + 4);
+
+        let mut cpu = load_code(&[
+            0xA9, 0x42, // LDA #0x42
+            0x85, 0x10, // STA 0x10
+        ]);
+        cpu.tick().unwrap(); // starts the LDA, which is stepped cycle by cycle
+        assert_eq!(
+            cpu.skip_instruction(),
+            Some(Instruction::new(Opcode::Sta, AddressingMode::ZeroPage))
+        );
+        assert_eq!(cpu.a, 0x42, "the instruction in flight is finished");
+        assert_eq!(cpu.pc, CODE_START + 4);
+        assert_eq!(cpu.memory[0x10], 0x00);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_save_and_load_state() {
+        let mut cpu = load_code(&[
+            0xA9, 0x42, // LDA #0x42
+            0x85, 0x10, // STA 0x10
+        ]);
+        cpu.run(RunLimit::Instructions(2));
+        let path = std::env::temp_dir().join(format!("state-{}.bin", std::process::id()));
+        cpu.save_state(&path).unwrap();
+
+        let mut loaded = Cpu::new(Memory::new());
+        loaded.load_state(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.snapshot(), cpu.snapshot());
+        assert_eq!(loaded.memory[0x10], 0x42);
+
+        std::fs::write(&path, b"NES\x1A\x02\x01").unwrap();
+        let err = loaded.load_state(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "not a save state");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_modify_write_dummy_write() {
+        let code = [
+            0xEE, 0x00, 0xD0, // INC 0xD000
+        ];
+        // reads are logged as None, writes as the written byte
+        for (variant, expected) in [
+            (Variant::Nmos6502, vec![None, Some(0x41), Some(0x42)]),
+            (Variant::Cmos65C02, vec![None, None, Some(0x42)]),
+        ] {
+            let accesses = Arc::new(Mutex::new(Vec::new()));
+            let read_log = accesses.clone();
+            let write_log = accesses.clone();
+            let mut cpu = load_code(&code);
+            cpu.variant = variant;
+            cpu.memory.map_register(
+                0xD000,
+                move || {
+                    read_log.lock().unwrap().push(None);
+                    0x41
+                },
+                move |data| write_log.lock().unwrap().push(Some(data)),
+            );
+
+            cpu.run(RunLimit::Instructions(1));
+            assert_eq!(*accesses.lock().unwrap(), expected, "{:?}", variant);
+            assert_eq!(cpu.cycles, 6);
+        }
+    }
+
+    #[cfg(feature = "send")]
+    #[test]
+    fn test_cpus_on_threads() {
+        let handles = (1..=4)
+            .map(|value| {
+                let mut cpu = load_code(&[
+                    0xA9, value, // LDA #value
+                    0x0A,  // ASL
+                    0x85, 0x10, // STA 0x10
+                ]);
+                std::thread::spawn(move || {
+                    cpu.run(RunLimit::Instructions(3));
+                    cpu.memory[0x10]
+                })
+            })
+            .collect::<Vec<_>>();
+        let results = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, [2, 4, 6, 8]);
+    }
 }
@@ -0,0 +1,265 @@
+use alloc::vec::Vec;
+
+use derive_more::Display;
+#[cfg(feature = "std")]
+use derive_more::Error;
+
+use crate::cpu::{Byte, Word};
+use crate::mem::Memory;
+
+const INES_MAGIC: [Byte; 4] = *b"NES\x1A";
+const INES_HEADER_LEN: usize = 16;
+const INES_TRAINER_LEN: usize = 512;
+const INES_TRAINER_START: Word = 0x7000;
+const INES_PRG_BANK_LEN: usize = 0x4000;
+const INES_CHR_BANK_LEN: usize = 0x2000;
+
+/// An error in an Intel HEX file. `record` is the 1-based number of the record
+/// (the non-empty line) that failed to parse.
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum IhexError {
+    #[display(fmt = "record {}: missing start code ':'", record)]
+    MissingStartCode { record: usize },
+    #[display(fmt = "record {}: invalid hex digit", record)]
+    InvalidDigit { record: usize },
+    #[display(fmt = "record {}: expected {} bytes, got {}", record, expected, actual)]
+    BadLength {
+        record: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[display(
+        fmt = "record {}: checksum is {:#04X}, expected {:#04X}",
+        record,
+        actual,
+        expected
+    )]
+    BadChecksum {
+        record: usize,
+        expected: Byte,
+        actual: Byte,
+    },
+    #[display(fmt = "record {}: unknown record type {:#04X}", record, record_type)]
+    UnknownRecordType { record: usize, record_type: Byte },
+}
+
+/// The part of an iNES header that failed validation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InesField {
+    /// The `NES<EOF>` signature in bytes 0-3.
+    Magic,
+    /// The number of 16KB PRG ROM banks in byte 4.
+    PrgRomSize,
+    /// The mapper number in the upper nibbles of bytes 6 and 7.
+    Mapper,
+}
+
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum InesError {
+    #[display(fmt = "invalid iNES header field {:?}", field)]
+    InvalidHeader { field: InesField },
+    #[display(fmt = "expected at least {} bytes, got {}", expected, actual)]
+    Truncated { expected: usize, actual: usize },
+}
+
+/// Loads the data records of an Intel HEX file into memory, stopping at the
+/// end-of-file record. Addresses wrap around within the 64KB address space.
+pub fn load_ihex(memory: &mut Memory, source: &str) -> Result<(), IhexError> {
+    let records = source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    for (index, line) in records.enumerate() {
+        let record = index + 1;
+        let digits = line
+            .strip_prefix(':')
+            .ok_or(IhexError::MissingStartCode { record })?;
+        let bytes = parse_hex(digits).ok_or(IhexError::InvalidDigit { record })?;
+
+        // length, address (2), type, data..., checksum
+        let expected = bytes.first().map_or(5, |&len| len as usize + 5);
+        if bytes.len() != expected {
+            return Err(IhexError::BadLength {
+                record,
+                expected,
+                actual: bytes.len(),
+            });
+        }
+
+        let (&actual, body) = bytes.split_last().unwrap();
+        let sum = body
+            .iter()
+            .fold(0 as Byte, |sum, &byte| sum.wrapping_add(byte));
+        let expected = sum.wrapping_neg();
+        if actual != expected {
+            return Err(IhexError::BadChecksum {
+                record,
+                expected,
+                actual,
+            });
+        }
+
+        let address = Word::from_be_bytes([body[1], body[2]]);
+        match body[3] {
+            0x00 => {
+                for (offset, &byte) in body[4..].iter().enumerate() {
+                    memory[address.wrapping_add(offset as Word) as usize] = byte;
+                }
+            }
+            0x01 => break,
+            record_type => {
+                return Err(IhexError::UnknownRecordType {
+                    record,
+                    record_type,
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Loads an iNES ROM image that uses mapper 0 (NROM).
+///
+/// The PRG ROM is added as a bank and mapped into `0x8000..=0xFFFF`, so a single
+/// 16KB bank is mirrored into both halves. A trainer is loaded at `0x7000`. The
+/// CHR ROM is only validated, since there is no PPU to map it into.
+pub fn load_ines(memory: &mut Memory, bytes: &[Byte]) -> Result<(), InesError> {
+    let truncated = |expected: usize| InesError::Truncated {
+        expected,
+        actual: bytes.len(),
+    };
+    let invalid = |field: InesField| InesError::InvalidHeader { field };
+
+    let header = bytes
+        .get(..INES_HEADER_LEN)
+        .ok_or_else(|| truncated(INES_HEADER_LEN))?;
+    if header[..4] != INES_MAGIC {
+        return Err(invalid(InesField::Magic));
+    }
+    let prg_banks = header[4] as usize;
+    if !(1..=2).contains(&prg_banks) {
+        return Err(invalid(InesField::PrgRomSize));
+    }
+    let mapper = (header[7] & 0xF0) | (header[6] >> 4);
+    if mapper != 0 {
+        return Err(invalid(InesField::Mapper));
+    }
+
+    let has_trainer = header[6] & 0b0000_0100 != 0;
+    let trainer_len = if has_trainer { INES_TRAINER_LEN } else { 0 };
+    let prg_start = INES_HEADER_LEN + trainer_len;
+    let prg_end = prg_start + prg_banks * INES_PRG_BANK_LEN;
+    let chr_end = prg_end + header[5] as usize * INES_CHR_BANK_LEN;
+    if bytes.len() < chr_end {
+        return Err(truncated(chr_end));
+    }
+
+    if has_trainer {
+        memory.load(INES_TRAINER_START, &bytes[INES_HEADER_LEN..prg_start]);
+    }
+    let prg = memory.add_bank(bytes[prg_start..prg_end].to_vec());
+    memory.switch_bank(0x8000..=0xFFFF, prg);
+    Ok(())
+}
+
+fn parse_hex(digits: &str) -> Option<Vec<Byte>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| Byte::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn test_load_ihex() {
+        let mut memory = Memory::new();
+        load_ihex(
+            &mut memory,
+            "
+            :03C00000A9018D06
+            :02FFFC0000C043
+            :00000001FF
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(memory.peek(0xC000), 0xA9);
+        assert_eq!(memory.peek(0xC002), 0x8D);
+        assert_eq!(memory.peek(0xFFFD), 0xC0);
+    }
+
+    #[test]
+    fn test_load_ihex_errors() {
+        let mut memory = Memory::new();
+        assert_eq!(
+            load_ihex(&mut memory, ":03C00000A9018D06\n:02FFFC0000C042"),
+            Err(IhexError::BadChecksum {
+                record: 2,
+                expected: 0x43,
+                actual: 0x42,
+            })
+        );
+        assert_eq!(
+            load_ihex(&mut memory, ":03C00000A9018D"),
+            Err(IhexError::BadLength {
+                record: 1,
+                expected: 8,
+                actual: 7,
+            })
+        );
+        assert_eq!(
+            load_ihex(&mut memory, ":00000004FC"),
+            Err(IhexError::UnknownRecordType {
+                record: 1,
+                record_type: 0x04,
+            })
+        );
+    }
+
+    #[test]
+    fn test_load_ines() {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x00];
+        rom.resize(INES_HEADER_LEN, 0);
+        rom.resize(INES_HEADER_LEN + INES_PRG_BANK_LEN, 0xEA);
+        let mut memory = Memory::new();
+        load_ines(&mut memory, &rom).unwrap();
+        assert_eq!(memory.peek(0x8000), 0xEA);
+        assert_eq!(memory.peek(0xFFFF), 0xEA, "16KB PRG ROM is mirrored");
+
+        let mut bad_magic = rom.clone();
+        bad_magic[3] = 0x00;
+        assert_eq!(
+            load_ines(&mut memory, &bad_magic),
+            Err(InesError::InvalidHeader {
+                field: InesField::Magic
+            })
+        );
+
+        let mut mmc1 = rom.clone();
+        mmc1[6] = 0x10;
+        assert_eq!(
+            load_ines(&mut memory, &mmc1),
+            Err(InesError::InvalidHeader {
+                field: InesField::Mapper
+            })
+        );
+
+        assert_eq!(
+            load_ines(&mut memory, &rom[..0x100]),
+            Err(InesError::Truncated {
+                expected: INES_HEADER_LEN + INES_PRG_BANK_LEN,
+                actual: 0x100,
+            })
+        );
+    }
+}
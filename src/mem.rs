@@ -1,15 +1,208 @@
-use std::fmt::{Debug, Formatter};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::{Debug, Formatter};
+use core::ops::{Index, IndexMut, RangeInclusive};
 
 use crate::cpu::{Byte, Word};
 
 pub const MAX_MEMORY: Word = Word::MAX;
+/// The default address of the memory-mapped output register, see
+/// [`Memory::set_output_address`]. Bytes written to it are passed to the output sink
+/// (see [`Memory::set_output_sink`]), and reading it returns the output status (see
+/// [`Memory::set_output_status`]).
+pub const OUTPUT_ADDRESS: Word = 0x0F;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A single access on the address bus, as performed in one CPU cycle.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BusCycle {
+    pub address: Word,
+    pub data: Byte,
+    pub kind: AccessKind,
+}
+
+impl BusCycle {
+    pub fn read(address: Word, data: Byte) -> Self {
+        Self {
+            address,
+            data,
+            kind: AccessKind::Read,
+        }
+    }
+
+    pub fn write(address: Word, data: Byte) -> Self {
+        Self {
+            address,
+            data,
+            kind: AccessKind::Write,
+        }
+    }
+}
+
+/// `Send` with the `send` feature and no bound otherwise. Devices and the
+/// closures that are passed to [`Memory::map_register`] must implement it, so that
+/// with the feature, a [`Cpu`](crate::cpu::Cpu) can be moved to another thread.
+#[cfg(feature = "send")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "send")]
+impl<T: Send> MaybeSend for T {}
+
+/// `Send` with the `send` feature and no bound otherwise. Devices and the
+/// closures that are passed to [`Memory::map_register`] must implement it, so that
+/// with the feature, a [`Cpu`](crate::cpu::Cpu) can be moved to another thread.
+#[cfg(not(feature = "send"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "send"))]
+impl<T> MaybeSend for T {}
+
+/// Receives the kind, address and data of a memory access, see
+/// [`Memory::set_access_log`]. It must be `Send` with the `send` feature.
+#[cfg(feature = "send")]
+pub type AccessLog = Box<dyn FnMut(AccessKind, Word, Byte) + Send>;
+/// Receives the kind, address and data of a memory access, see
+/// [`Memory::set_access_log`]. It must be `Send` with the `send` feature.
+#[cfg(not(feature = "send"))]
+pub type AccessLog = Box<dyn FnMut(AccessKind, Word, Byte)>;
+
+/// Receives the bytes written to the output register, see
+/// [`Memory::set_output_sink`]. It must be `Send` with the `send` feature.
+#[cfg(feature = "send")]
+pub type OutputSink = Box<dyn FnMut(Byte) + Send>;
+/// Receives the bytes written to the output register, see
+/// [`Memory::set_output_sink`]. It must be `Send` with the `send` feature.
+#[cfg(not(feature = "send"))]
+pub type OutputSink = Box<dyn FnMut(Byte)>;
+
+/// A memory-mapped device, see [`Memory::attach_device`].
+pub trait Device: MaybeSend {
+    /// Reads the byte at `address`, or returns `None` to pass the read through to
+    /// the next device, or eventually to RAM.
+    fn read(&self, address: Word) -> Option<Byte>;
+
+    /// Writes the byte at `address` and returns whether the device handled it. If
+    /// not, the write is passed through like a read.
+    fn write(&mut self, address: Word, data: Byte) -> bool;
+
+    /// Advances the device by the given number of CPU cycles.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Whether the device asserts the IRQ line.
+    fn irq(&self) -> bool {
+        false
+    }
+}
+
+/// A single register that is backed by host closures, see [`Memory::map_register`].
+/// Reads mutate the closure, which [`Device::read`] doesn't allow for by itself.
+struct Register<R, W> {
+    read: RefCell<R>,
+    write: W,
+}
+
+impl<R: FnMut() -> Byte + MaybeSend, W: FnMut(Byte) + MaybeSend> Device for Register<R, W> {
+    fn read(&self, _: Word) -> Option<Byte> {
+        Some((self.read.borrow_mut())())
+    }
+
+    fn write(&mut self, _: Word, data: Byte) -> bool {
+        (self.write)(data);
+        true
+    }
+}
+
+/// A device together with the addresses it is attached to.
+struct Attached {
+    range: RangeInclusive<Word>,
+    name: String,
+    priority: i32,
+    device: Box<dyn Device>,
+}
+
+/// What happens when the CPU writes into a read-only region, see
+/// [`Memory::set_read_only`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum RomWritePolicy {
+    /// The write is silently dropped, as on real hardware.
+    #[default]
+    Ignore,
+    /// The write is dropped and [`Cpu::run`](crate::cpu::Cpu::run) stops with
+    /// [`StopReason::RomWrite`](crate::cpu::StopReason::RomWrite).
+    Trap,
+}
+
+/// What accesses to addresses above the RAM of a [`Memory::with_size`] see.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Unmapped {
+    /// The RAM repeats throughout the address space, as when the upper address
+    /// lines aren't decoded.
+    Mirror,
+    /// Reads return the given byte and writes are ignored.
+    OpenBus(Byte),
+}
 
 pub struct Memory {
-    data: [u8; MAX_MEMORY as usize],
+    /// The RAM, which covers the whole address space unless created with
+    /// [`Memory::with_size`]. It is shared with clones and snapshots until either
+    /// side writes to it, see [`Memory::ram`].
+    data: Arc<[Byte]>,
+    unmapped: Unmapped,
+
+    banks: Vec<Vec<Byte>>,
+    /// Address ranges that are currently backed by a bank instead of `data`.
+    windows: Vec<(RangeInclusive<Word>, usize)>,
+
+    /// The address of the output register, [`OUTPUT_ADDRESS`] by default.
+    output_address: Word,
+    /// Receives the bytes written to the output register. If there is none, they're
+    /// printed to stdout.
+    output_sink: Option<OutputSink>,
+    /// The value that reads of the output register return.
+    output_status: Byte,
+
+    /// The attached devices, ordered from highest to lowest priority.
+    devices: Vec<Attached>,
+
+    read_only: Vec<(RangeInclusive<Word>, RomWritePolicy)>,
+    /// The first write into a trapping read-only region that hasn't been taken yet.
+    rom_write: Option<(Word, Byte)>,
+
+    /// Receives every read and write, see [`Memory::set_access_log`].
+    access_log: Option<AccessLog>,
+}
+
+/// Clones the contents of the memory. The output sink, devices and access log can't
+/// be cloned, so the clone prints its output to stdout and has neither devices nor
+/// an access log.
+impl Clone for Memory {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            unmapped: self.unmapped,
+            banks: self.banks.clone(),
+            windows: self.windows.clone(),
+            output_address: self.output_address,
+            output_sink: None,
+            output_status: self.output_status,
+            devices: Vec::new(),
+            read_only: self.read_only.clone(),
+            rom_write: self.rom_write,
+            access_log: None,
+        }
+    }
 }
 
 impl Debug for Memory {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Memory").finish()
     }
 }
@@ -20,25 +213,588 @@ impl Default for Memory {
     }
 }
 
+impl Index<usize> for Memory {
+    type Output = Byte;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.data[self.index_of(index)]
+    }
+}
+
+impl IndexMut<usize> for Memory {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        let index = self.index_of(index);
+        &mut Arc::make_mut(&mut self.data)[index]
+    }
+}
+
 impl Memory {
     pub fn new() -> Self {
+        Self::with_size(MAX_MEMORY as usize + 1, Unmapped::Mirror)
+    }
+
+    /// Creates a memory with only `size` bytes of RAM at the bottom of the address
+    /// space. Accesses above it are handled according to `unmapped`.
+    pub fn with_size(size: usize, unmapped: Unmapped) -> Self {
+        assert!(
+            (1..=MAX_MEMORY as usize + 1).contains(&size),
+            "invalid memory size {:#X}",
+            size
+        );
+
         Self {
-            data: [0; MAX_MEMORY as usize],
+            data: vec![0; size].into(),
+            unmapped,
+
+            banks: Vec::new(),
+            windows: Vec::new(),
+            output_address: OUTPUT_ADDRESS,
+            output_sink: None,
+            output_status: 0x00,
+            devices: Vec::new(),
+            read_only: Vec::new(),
+            rom_write: None,
+            access_log: None,
         }
     }
 
-    pub fn read(&self, address: Word) -> Byte {
-        if address == 0x0F {
-            // can't read from stdout
-            panic!("read at 0x0F");
+    /// Adds a bank that can be mapped into the address space with
+    /// [`Memory::switch_bank`] and returns its index.
+    pub fn add_bank(&mut self, bank: Vec<Byte>) -> usize {
+        self.banks.push(bank);
+        self.banks.len() - 1
+    }
+
+    /// Maps the given bank into `window`, replacing the bank that was previously
+    /// mapped into exactly that window. Accesses within the window index into the
+    /// bank, which is mirrored if it's smaller than the window.
+    pub fn switch_bank(&mut self, window: RangeInclusive<Word>, bank: usize) {
+        assert!(
+            bank < self.banks.len() && !self.banks[bank].is_empty(),
+            "no bank {}",
+            bank
+        );
+
+        match self.windows.iter_mut().find(|(range, _)| *range == window) {
+            Some((_, mapped)) => *mapped = bank,
+            None => self.windows.push((window, bank)),
+        }
+    }
+
+    /// Returns the bank and the offset into it that backs the given address, if
+    /// the address is within a bank window.
+    fn banked(&self, address: Word) -> Option<(usize, usize)> {
+        self.windows
+            .iter()
+            .find(|(window, _)| window.contains(&address))
+            .map(|(window, bank)| {
+                let offset = (address - window.start()) as usize;
+                (*bank, offset % self.banks[*bank].len())
+            })
+    }
+
+    /// Returns the index into the RAM that backs `address`, or `None` if the
+    /// address is open bus.
+    fn ram_index(&self, address: Word) -> Option<usize> {
+        let address = address as usize;
+        match self.unmapped {
+            _ if address < self.data.len() => Some(address),
+            Unmapped::Mirror => Some(address % self.data.len()),
+            Unmapped::OpenBus(_) => None,
+        }
+    }
+
+    fn index_of(&self, index: usize) -> usize {
+        Word::try_from(index)
+            .ok()
+            .and_then(|address| self.ram_index(address))
+            .unwrap_or_else(|| panic!("{:#06X} is not backed by RAM", index))
+    }
+
+    /// Copies the given bytes into memory, starting at `start`. Like indexing, this
+    /// bypasses banks and the memory-mapped registers.
+    pub fn load(&mut self, start: Word, bytes: &[Byte]) {
+        assert!(
+            start as usize + bytes.len() <= MAX_MEMORY as usize + 1,
+            "{} bytes don't fit at {:#06X}",
+            bytes.len(),
+            start
+        );
+        for (address, &byte) in (start..=MAX_MEMORY).zip(bytes) {
+            if let Some(index) = self.ram_index(address) {
+                Arc::make_mut(&mut self.data)[index] = byte;
+            }
+        }
+    }
+
+    /// Like [`Memory::load`], but zeroes the whole RAM first, so that nothing but
+    /// the given bytes remains of the previous contents. Banks aren't cleared.
+    pub fn load_exclusive(&mut self, start: Word, bytes: &[Byte]) {
+        Arc::make_mut(&mut self.data).fill(0);
+        self.load(start, bytes);
+    }
+
+    /// Loads a C64 style PRG file, whose first two bytes are the little-endian load
+    /// address of the rest, with [`Memory::load`]. Returns the load address, or
+    /// `None` without loading anything if the file is too short to contain one or
+    /// the rest doesn't fit between the load address and the end of memory.
+    pub fn load_prg(&mut self, bytes: &[Byte]) -> Option<Word> {
+        let [low, high, payload @ ..] = bytes else {
+            return None;
+        };
+        let start = Word::from_le_bytes([*low, *high]);
+        if start as usize + payload.len() > MAX_MEMORY as usize + 1 {
+            return None;
+        }
+        self.load(start, payload);
+        Some(start)
+    }
+
+    /// Borrows the backing RAM in `range` for fast bulk access. The range must lie
+    /// within the RAM, mirrors can't be borrowed.
+    ///
+    /// This bypasses the memory-mapped registers as well as banking, so within a
+    /// bank window the slice shows the RAM that is hidden behind the bank.
+    pub fn slice(&self, range: RangeInclusive<Word>) -> &[Byte] {
+        &self.data[*range.start() as usize..=*range.end() as usize]
+    }
+
+    /// Shares the RAM without copying it. The memory copies its RAM before the next
+    /// write, so the returned RAM never changes.
+    pub fn ram(&self) -> Arc<[Byte]> {
+        self.data.clone()
+    }
+
+    /// Replaces the RAM, which also changes its size to that of `ram`. Like
+    /// [`Memory::ram`], this doesn't copy it.
+    pub fn set_ram(&mut self, ram: Arc<[Byte]>) {
+        assert!(
+            (1..=MAX_MEMORY as usize + 1).contains(&ram.len()),
+            "invalid memory size {:#X}",
+            ram.len()
+        );
+        self.data = ram;
+    }
+
+    /// Computes the CRC-32 (IEEE 802.3, as used by zip and PNG) of the bytes in
+    /// `range`, which are read like [`Memory::slice`] does.
+    pub fn checksum(&self, range: RangeInclusive<Word>) -> u32 {
+        let crc = self.slice(range).iter().fold(!0u32, |crc, &byte| {
+            (0..8).fold(crc ^ byte as u32, |crc, _| {
+                if crc & 1 != 0 {
+                    crc >> 1 ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                }
+            })
+        });
+        !crc
+    }
+
+    /// Returns the address of the first occurrence of `needle` at or after `start`.
+    /// Like [`Memory::slice`], this searches the backing RAM, without its mirrors.
+    pub fn find(&self, needle: &[Byte], start: Word) -> Option<Word> {
+        if needle.is_empty() {
+            return Some(start);
         }
-        self.data[address as usize]
+        self.data
+            .get(start as usize..)?
+            .windows(needle.len())
+            .position(|window| window == needle)
+            .map(|offset| start + offset as Word)
     }
 
+    /// Reads a byte without any of the side effects that [`Memory::read`] might have.
+    pub fn peek(&self, address: Word) -> Byte {
+        match self.banked(address) {
+            Some((bank, offset)) => self.banks[bank][offset],
+            None => match (self.ram_index(address), self.unmapped) {
+                (Some(index), _) => self.data[index],
+                (None, Unmapped::OpenBus(value)) => value,
+                (None, Unmapped::Mirror) => unreachable!("mirrored memory is always backed"),
+            },
+        }
+    }
+
+    /// Returns `(address, self_byte, other_byte)` for every address at which the
+    /// two memories differ, as seen through [`Memory::peek`].
+    pub fn diff(&self, other: &Memory) -> Vec<(Word, Byte, Byte)> {
+        (0..=MAX_MEMORY)
+            .map(|address| (address, self.peek(address), other.peek(address)))
+            .filter(|(_, this, other)| this != other)
+            .collect()
+    }
+
+    /// Moves the output register from [`OUTPUT_ADDRESS`] to `address`, for test ROMs
+    /// that print characters through a different address.
+    pub fn set_output_address(&mut self, address: Word) {
+        self.output_address = address;
+    }
+
+    /// Passes all bytes that are written to the output register to `sink` instead of
+    /// printing them to stdout.
+    pub fn set_output_sink(&mut self, sink: OutputSink) {
+        self.output_sink = Some(sink);
+    }
+
+    /// Sets the value that reads of the output register return, which is `0x00` by
+    /// default.
+    pub fn set_output_status(&mut self, status: Byte) {
+        self.output_status = status;
+    }
+
+    /// Calls `log` for every [`Memory::read`] and [`Memory::write`], which are all
+    /// accesses that the CPU makes, with the byte that was read or written.
+    pub fn set_access_log(&mut self, log: AccessLog) {
+        self.access_log = Some(log);
+    }
+
+    /// Reads a byte as the CPU does, which includes the memory-mapped registers.
+    pub fn read(&mut self, address: Word) -> Byte {
+        let data = self.read_io(address).unwrap_or_else(|| self.peek(address));
+        if let Some(log) = &mut self.access_log {
+            log(AccessKind::Read, address, data);
+        }
+        data
+    }
+
+    /// Writes a byte as the CPU does, which includes the memory-mapped registers.
     pub fn write(&mut self, address: Word, data: Byte) {
-        if address == 0x0F {
-            print!("{}", data as char);
+        if let Some(log) = &mut self.access_log {
+            log(AccessKind::Write, address, data);
+        }
+        if self.write_io(address, data) {
+            return;
         }
-        self.data[address as usize] = data;
+        match self
+            .read_only
+            .iter()
+            .find(|(range, _)| range.contains(&address))
+        {
+            Some((_, RomWritePolicy::Ignore)) => {}
+            Some((_, RomWritePolicy::Trap)) => {
+                self.rom_write.get_or_insert((address, data));
+            }
+            None => self.poke(address, data),
+        }
+    }
+
+    /// Makes the CPU's writes to `range` have no effect. [`Memory::load`] and
+    /// [`Memory::poke`] can still modify it.
+    pub fn set_read_only(&mut self, range: RangeInclusive<Word>, policy: RomWritePolicy) {
+        self.read_only.push((range, policy));
+    }
+
+    /// Returns and clears the address and value of the first write into a read-only
+    /// region with [`RomWritePolicy::Trap`] since the last call.
+    pub fn take_rom_write(&mut self) -> Option<(Word, Byte)> {
+        self.rom_write.take()
+    }
+
+    /// Attaches a device to the addresses in `range`. Where devices overlap, the one
+    /// with the highest `priority` gets to handle an access first, and devices with
+    /// equal priority in the order they were attached. Devices take precedence over
+    /// the output register, banks and RAM. The `name` is only used to label the
+    /// device in [`Memory::mappings`].
+    pub fn attach_device(
+        &mut self,
+        range: RangeInclusive<Word>,
+        priority: i32,
+        name: &str,
+        device: Box<dyn Device>,
+    ) {
+        let index = self
+            .devices
+            .partition_point(|attached| attached.priority >= priority);
+        self.devices.insert(
+            index,
+            Attached {
+                range,
+                name: name.into(),
+                priority,
+                device,
+            },
+        );
+    }
+
+    /// Attaches a single register at `address`, whose reads return the result of
+    /// `read` and whose writes are passed to `write`. This is a lightweight
+    /// alternative to implementing a [`Device`].
+    pub fn map_register(
+        &mut self,
+        address: Word,
+        read: impl FnMut() -> Byte + MaybeSend + 'static,
+        write: impl FnMut(Byte) + MaybeSend + 'static,
+    ) {
+        let register = Register {
+            read: RefCell::new(read),
+            write,
+        };
+        let name = format!("register ${:04X}", address);
+        self.attach_device(address..=address, 0, &name, Box::new(register));
+    }
+
+    /// The ranges and names of the attached devices, in the order in which they get
+    /// to handle accesses.
+    pub fn mappings(&self) -> Vec<(RangeInclusive<Word>, String)> {
+        self.devices
+            .iter()
+            .map(|attached| (attached.range.clone(), attached.name.clone()))
+            .collect()
+    }
+
+    /// Advances all attached devices by the given number of cycles, see [`Device::tick`].
+    pub fn tick_devices(&mut self, cycles: u64) {
+        for attached in &mut self.devices {
+            attached.device.tick(cycles);
+        }
+    }
+
+    /// Whether any attached device asserts the IRQ line.
+    pub fn device_irq(&self) -> bool {
+        self.devices.iter().any(|attached| attached.device.irq())
+    }
+
+    /// Reads a memory-mapped register, or returns `None` if there is none at `address`.
+    fn read_io(&self, address: Word) -> Option<Byte> {
+        self.devices
+            .iter()
+            .filter(|attached| attached.range.contains(&address))
+            .find_map(|attached| attached.device.read(address))
+            .or_else(|| (address == self.output_address).then_some(self.output_status))
+    }
+
+    /// Writes a memory-mapped register and returns whether there was one at `address`.
+    fn write_io(&mut self, address: Word, data: Byte) -> bool {
+        let handled = self
+            .devices
+            .iter_mut()
+            .filter(|attached| attached.range.contains(&address))
+            .any(|attached| attached.device.write(address, data));
+        if handled {
+            return true;
+        }
+
+        if address != self.output_address {
+            return false;
+        }
+        match &mut self.output_sink {
+            Some(sink) => sink(data),
+            #[cfg(feature = "std")]
+            None => print!("{}", data as char),
+            #[cfg(not(feature = "std"))]
+            None => {}
+        }
+        true
+    }
+
+    /// Writes a byte without any of the side effects that [`Memory::write`] might have.
+    pub fn poke(&mut self, address: Word, data: Byte) {
+        match self.banked(address) {
+            Some((bank, offset)) => self.banks[bank][offset] = data,
+            None => {
+                if let Some(index) = self.ram_index(address) {
+                    Arc::make_mut(&mut self.data)[index] = data;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_switch_bank() {
+        let mut memory = Memory::new();
+        let first = memory.add_bank(vec![0x11; 0x8000]);
+        let second = memory.add_bank(vec![0x22; 0x4000]);
+
+        memory.switch_bank(0x8000..=0xFFFF, first);
+        assert_eq!(memory.read(0x8000), 0x11);
+        assert_eq!(memory.read(0xFFFF), 0x11);
+
+        memory.switch_bank(0x8000..=0xFFFF, second);
+        assert_eq!(memory.read(0x8000), 0x22);
+        assert_eq!(memory.read(0xFFFF), 0x22);
+
+        memory.write(0xC000, 0x33);
+        assert_eq!(memory.read(0x8000), 0x33, "16KB bank is mirrored");
+        memory.switch_bank(0x8000..=0xFFFF, first);
+        assert_eq!(memory.read(0xC000), 0x11);
+        assert_eq!(memory.read(0x7FFF), 0x00, "outside of the window");
+    }
+
+    #[test]
+    fn test_with_size() {
+        let mut mirrored = Memory::with_size(0x4000, Unmapped::Mirror);
+        mirrored.write(0x3FFF, 0x11);
+        assert_eq!(mirrored.read(0x3FFF), 0x11);
+        assert_eq!(mirrored.read(0x7FFF), 0x11);
+        assert_eq!(mirrored.read(0xFFFF), 0x11);
+        mirrored.write(0x4000, 0x22);
+        assert_eq!(mirrored.read(0x0000), 0x22);
+
+        let mut open_bus = Memory::with_size(0x4000, Unmapped::OpenBus(0xFF));
+        open_bus.write(0x3FFF, 0x11);
+        open_bus.write(0x7FFF, 0x22);
+        assert_eq!(open_bus.read(0x3FFF), 0x11);
+        assert_eq!(open_bus.read(0x7FFF), 0xFF);
+    }
+
+    #[test]
+    fn test_device_priority() {
+        /// Handles every access with a fixed value.
+        struct Fixed(Byte);
+        impl Device for Fixed {
+            fn read(&self, _: Word) -> Option<Byte> {
+                Some(self.0)
+            }
+            fn write(&mut self, _: Word, _: Byte) -> bool {
+                true
+            }
+        }
+
+        /// Only handles the first 16 addresses of its range.
+        struct Registers;
+        impl Device for Registers {
+            fn read(&self, address: Word) -> Option<Byte> {
+                (address & 0x00F0 == 0).then_some(0x22)
+            }
+            fn write(&mut self, _: Word, _: Byte) -> bool {
+                false
+            }
+        }
+
+        let mut memory = Memory::new();
+        memory.attach_device(0x8000..=0x8FFF, 0, "fixed", Box::new(Fixed(0x11)));
+        memory.attach_device(0x8000..=0x80FF, 1, "registers", Box::new(Registers));
+        assert_eq!(
+            memory.mappings(),
+            vec![
+                (0x8000..=0x80FF, "registers".to_string()),
+                (0x8000..=0x8FFF, "fixed".to_string()),
+            ]
+        );
+
+        assert_eq!(memory.read(0x8005), 0x22);
+        assert_eq!(memory.read(0x8050), 0x11, "passed through");
+        assert_eq!(memory.read(0x8500), 0x11);
+        assert_eq!(memory.read(0x9000), 0x00, "RAM");
+
+        memory.write(0x8005, 0x33);
+        assert_eq!(
+            memory.peek(0x8005),
+            0x00,
+            "the write was handled by a device"
+        );
+    }
+
+    #[test]
+    fn test_map_register() {
+        let mut counter = 0;
+        let written = Arc::new(AtomicU8::new(0));
+        let sink = written.clone();
+        let mut memory = Memory::new();
+        memory.map_register(
+            0xD000,
+            move || {
+                counter += 1;
+                counter
+            },
+            move |data| sink.store(data, Ordering::Relaxed),
+        );
+
+        assert_eq!(memory.read(0xD000), 1);
+        assert_eq!(memory.read(0xD000), 2);
+        assert_eq!(memory.read(0xD000), 3);
+        assert_eq!(memory.read(0xD001), 0x00, "RAM");
+
+        memory.write(0xD000, 0x42);
+        assert_eq!(written.load(Ordering::Relaxed), 0x42);
+        assert_eq!(memory.peek(0xD000), 0x00);
+        assert_eq!(
+            memory.mappings(),
+            vec![(0xD000..=0xD000, "register $D000".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_slice() {
+        let mut memory = Memory::new();
+        memory.load(0x0200, &[0x01, 0x02, 0x03, 0x04]);
+
+        let slice = memory.slice(0x0200..=0x0203);
+        assert_eq!(slice.len(), 4);
+        assert_eq!(slice.first(), Some(&0x01));
+        assert_eq!(slice.last(), Some(&0x04));
+        assert_eq!(memory.slice(0x0000..=0xFFFF).len(), 0x10000);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut memory = Memory::new();
+        memory.load(0x1234, &[0xDE, 0xAD, 0xBE]);
+        memory.load(0x2000, &[0xDE, 0xAD, 0xBE]);
+
+        assert_eq!(memory.find(&[0xDE, 0xAD, 0xBE], 0x0000), Some(0x1234));
+        assert_eq!(memory.find(&[0xDE, 0xAD, 0xBE], 0x1235), Some(0x2000));
+        assert_eq!(memory.find(&[0xDE, 0xAD, 0xEF], 0x0000), None);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut before = Memory::new();
+        before.load(0x0200, &[0x01, 0x02, 0x03]);
+        let mut after = before.clone();
+        after.write(0x0201, 0x22);
+        after.write(0xFFFF, 0xFF);
+
+        assert_eq!(
+            before.diff(&after),
+            vec![(0x0201, 0x02, 0x22), (0xFFFF, 0x00, 0xFF)]
+        );
+        assert!(after.diff(&after.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut memory = Memory::new();
+        memory.load(0x0200, b"123456789");
+        assert_eq!(memory.checksum(0x0200..=0x0208), 0xCBF4_3926);
+        assert_eq!(memory.checksum(0x0300..=0x0300), 0xD202_EF8D);
+    }
+
+    #[test]
+    fn test_load_prg() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.load_prg(&[0x01, 0x08, 0xA9, 0x42]), Some(0x0801));
+        assert_eq!(memory.slice(0x0800..=0x0803), &[0x00, 0xA9, 0x42, 0x00]);
+
+        assert_eq!(memory.load_prg(&[0x01]), None);
+        assert_eq!(memory.load_prg(&[]), None);
+
+        let mut prg = [0xAA; 2 + 32];
+        prg[..2].copy_from_slice(&[0xF0, 0xFF]);
+        assert_eq!(memory.load_prg(&prg), None);
+        assert_eq!(memory.peek(0xFFF0), 0x00);
+        assert_eq!(memory.load_prg(&prg[..2 + 16]), Some(0xFFF0));
+        assert_eq!(memory.peek(0xFFFF), 0xAA);
+    }
+
+    #[test]
+    fn test_load_exclusive() {
+        let mut memory = Memory::new();
+        memory.load(0x0200, &[0x55]);
+        memory.load(0xC000, &[0xFF, 0xFF, 0xFF]);
+
+        memory.load_exclusive(0xC000, &[0xA9, 0x42]);
+        assert_eq!(memory.peek(0x0200), 0x00);
+        assert_eq!(memory.slice(0xC000..=0xC002), &[0xA9, 0x42, 0x00]);
     }
 }
@@ -1,6 +1,12 @@
-use derive_more::{Constructor, Display, Error};
+use alloc::format;
+use core::fmt::{Display, Formatter};
 
-use crate::cpu::Byte;
+#[cfg(feature = "std")]
+use derive_more::Error;
+use derive_more::{Constructor, Display};
+
+use crate::cpu::{Byte, Word};
+use crate::mem::Memory;
 
 #[derive(Constructor, Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Instruction {
@@ -8,227 +14,484 @@ pub struct Instruction {
     pub addressing_mode: AddressingMode,
 }
 
-#[derive(Error, Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Error))]
 pub struct DecodeError;
 
+/// Whether the given byte decodes to an instruction that isn't part of the
+/// documented instruction set.
+pub fn is_undocumented(byte: Byte) -> bool {
+    OPCODES[byte as usize].is_some_and(|info| info.undocumented)
+}
+
+/// The category an opcode byte falls into, see [`classify_opcode`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OpcodeClass {
+    /// A documented instruction.
+    Legal(Instruction),
+    /// An undocumented instruction that is emulated nonetheless.
+    Illegal(Instruction),
+    /// A byte that the CPU can't execute. Undocumented opcodes that aren't emulated
+    /// are classified like this as well.
+    Jam,
+}
+
+/// Classifies the given opcode byte according to [`OPCODES`].
+pub fn classify_opcode(byte: Byte) -> OpcodeClass {
+    match OPCODES[byte as usize] {
+        Some(info) if info.undocumented => OpcodeClass::Illegal(info.instruction()),
+        Some(info) => OpcodeClass::Legal(info.instruction()),
+        None => OpcodeClass::Jam,
+    }
+}
+
+/// An instruction together with its operand bytes and the address it is located at,
+/// which is everything needed to render it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DecodedInstruction {
+    pub instruction: Instruction,
+    /// The operand bytes, of which only the first `len - 1` are meaningful.
+    pub operands: [Byte; 2],
+    /// The length of the instruction in bytes, including the opcode.
+    pub len: usize,
+    pub addr: Word,
+}
+
+/// Decodes the instruction at `addr`, or returns `None` if the byte there isn't
+/// a valid opcode. Memory is accessed through [`Memory::peek`].
+pub fn decode_at(memory: &Memory, addr: Word) -> Option<DecodedInstruction> {
+    Variant::default().decode_at(memory, addr)
+}
+
+/// The CPU model, which determines the decode table.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Variant {
+    /// The original NMOS 6502, see [`OPCODES`].
+    #[default]
+    Nmos6502,
+    /// The Rockwell/WDC 65C02, see [`OPCODES_65C02`].
+    Cmos65C02,
+    /// The Ricoh 2A03 of the NES, which decodes like the NMOS 6502 but has decimal
+    /// mode disabled in hardware.
+    Nes2A03,
+    /// The earliest NMOS 6502 revision, which shipped before ROR worked. ROR decodes
+    /// as usual, but shifts left like ASL without affecting the carry flag.
+    Nmos6502NoRor,
+}
+
+impl Variant {
+    /// The decode table of this variant.
+    pub fn opcodes(&self) -> &'static [Option<OpInfo>; 256] {
+        match self {
+            Self::Nmos6502 | Self::Nes2A03 | Self::Nmos6502NoRor => &OPCODES,
+            Self::Cmos65C02 => &OPCODES_65C02,
+        }
+    }
+
+    /// Whether ADC and SBC work on binary coded decimals when the decimal mode flag
+    /// is set.
+    pub fn has_decimal_mode(&self) -> bool {
+        !matches!(self, Self::Nes2A03)
+    }
+
+    /// Whether ROR rotates right, rather than behaving like the broken ROR of
+    /// [`Variant::Nmos6502NoRor`].
+    pub fn has_ror(&self) -> bool {
+        !matches!(self, Self::Nmos6502NoRor)
+    }
+
+    /// Like [`decode_at`], but decodes according to this variant's table.
+    pub fn decode_at(&self, memory: &Memory, addr: Word) -> Option<DecodedInstruction> {
+        let info = self.opcodes()[memory.peek(addr) as usize]?;
+        let len = info.bytes as usize;
+        let mut operands = [0; 2];
+        for (offset, operand) in operands.iter_mut().enumerate().take(len - 1) {
+            *operand = memory.peek(addr.wrapping_add(1 + offset as Word));
+        }
+        Some(DecodedInstruction {
+            instruction: info.instruction(),
+            operands,
+            len,
+            addr,
+        })
+    }
+}
+
+impl Instruction {
+    /// Returns the opcode byte that decodes to this instruction, if there is one.
+    /// Documented opcodes are preferred over undocumented ones.
+    pub fn encode(&self) -> Option<Byte> {
+        let decodes_to_self = |&byte: &Byte| Instruction::try_from(byte) == Ok(*self);
+        (0..=Byte::MAX)
+            .filter(|&byte| !is_undocumented(byte))
+            .find(decodes_to_self)
+            .or_else(|| (0..=Byte::MAX).find(decodes_to_self))
+    }
+
+    /// The length of this instruction in bytes, including the opcode.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        1 + self.addressing_mode.operand_len()
+    }
+}
+
 impl TryFrom<Byte> for Instruction {
     type Error = DecodeError;
 
     fn try_from(value: Byte) -> Result<Self, Self::Error> {
-        Ok(match value {
-            // ADC
-            0x69 => Self::new(Opcode::Adc, AddressingMode::Immediate),
-            0x65 => Self::new(Opcode::Adc, AddressingMode::ZeroPage),
-            0x75 => Self::new(Opcode::Adc, AddressingMode::ZeroPageX),
-            0x6D => Self::new(Opcode::Adc, AddressingMode::Absolute),
-            0x7D => Self::new(Opcode::Adc, AddressingMode::AbsoluteX),
-            0x79 => Self::new(Opcode::Adc, AddressingMode::AbsoluteY),
-            0x61 => Self::new(Opcode::Adc, AddressingMode::IndexedIndirect),
-            0x71 => Self::new(Opcode::Adc, AddressingMode::IndirectIndexed),
-            // AND
-            0x29 => Self::new(Opcode::And, AddressingMode::Immediate),
-            0x25 => Self::new(Opcode::And, AddressingMode::ZeroPage),
-            0x35 => Self::new(Opcode::And, AddressingMode::ZeroPageX),
-            0x2D => Self::new(Opcode::And, AddressingMode::Absolute),
-            0x3D => Self::new(Opcode::And, AddressingMode::AbsoluteX),
-            0x39 => Self::new(Opcode::And, AddressingMode::AbsoluteY),
-            0x21 => Self::new(Opcode::And, AddressingMode::IndexedIndirect),
-            0x31 => Self::new(Opcode::And, AddressingMode::IndirectIndexed),
-            // ASL
-            0x0A => Self::new(Opcode::Asl, AddressingMode::Accumulator),
-            0x06 => Self::new(Opcode::Asl, AddressingMode::ZeroPage),
-            0x16 => Self::new(Opcode::Asl, AddressingMode::ZeroPageX),
-            0x0E => Self::new(Opcode::Asl, AddressingMode::Absolute),
-            0x1E => Self::new(Opcode::Asl, AddressingMode::AbsoluteX),
-            // BCC
-            0x90 => Self::new(Opcode::Bcc, AddressingMode::Relative),
-            // BCS
-            0xB0 => Self::new(Opcode::Bcs, AddressingMode::Relative),
-            // BEQ
-            0xF0 => Self::new(Opcode::Beq, AddressingMode::Relative),
-            // BIT
-            0x24 => Self::new(Opcode::Bit, AddressingMode::ZeroPage),
-            0x2C => Self::new(Opcode::Bit, AddressingMode::Absolute),
-            // BMI
-            0x30 => Self::new(Opcode::Bmi, AddressingMode::Relative),
-            // BNE
-            0xD0 => Self::new(Opcode::Bne, AddressingMode::Relative),
-            // BPL
-            0x10 => Self::new(Opcode::Bpl, AddressingMode::Relative),
-            // BRK
-            0x00 => Self::new(Opcode::Brk, AddressingMode::Implicit),
-            // BVC
-            0x50 => Self::new(Opcode::Bvc, AddressingMode::Relative),
-            // BVS
-            0x70 => Self::new(Opcode::Bvs, AddressingMode::Relative),
-            // CLC
-            0x18 => Self::new(Opcode::Clc, AddressingMode::Implicit),
-            // CLD
-            0xD8 => Self::new(Opcode::Cld, AddressingMode::Implicit),
-            // CLI
-            0x58 => Self::new(Opcode::Cli, AddressingMode::Implicit),
-            // CLV
-            0xB8 => Self::new(Opcode::Clv, AddressingMode::Implicit),
-            // CMP
-            0xC9 => Self::new(Opcode::Cmp, AddressingMode::Immediate),
-            0xC5 => Self::new(Opcode::Cmp, AddressingMode::ZeroPage),
-            0xD5 => Self::new(Opcode::Cmp, AddressingMode::ZeroPageX),
-            0xCD => Self::new(Opcode::Cmp, AddressingMode::Absolute),
-            0xDD => Self::new(Opcode::Cmp, AddressingMode::AbsoluteX),
-            0xD9 => Self::new(Opcode::Cmp, AddressingMode::AbsoluteY),
-            0xC1 => Self::new(Opcode::Cmp, AddressingMode::IndexedIndirect),
-            0xD1 => Self::new(Opcode::Cmp, AddressingMode::IndirectIndexed),
-            // CPX
-            0xE0 => Self::new(Opcode::Cpx, AddressingMode::Immediate),
-            0xE4 => Self::new(Opcode::Cpx, AddressingMode::ZeroPage),
-            0xEC => Self::new(Opcode::Cpx, AddressingMode::Absolute),
-            // CPY
-            0xC0 => Self::new(Opcode::Cpy, AddressingMode::Immediate),
-            0xC4 => Self::new(Opcode::Cpy, AddressingMode::ZeroPage),
-            0xCC => Self::new(Opcode::Cpy, AddressingMode::Absolute),
-            // DEC
-            0xC6 => Self::new(Opcode::Dec, AddressingMode::ZeroPage),
-            0xD6 => Self::new(Opcode::Dec, AddressingMode::ZeroPageX),
-            0xCE => Self::new(Opcode::Dec, AddressingMode::Absolute),
-            0xDE => Self::new(Opcode::Dec, AddressingMode::AbsoluteX),
-            // DEX
-            0xCA => Self::new(Opcode::Dex, AddressingMode::Implicit),
-            // DEY
-            0x88 => Self::new(Opcode::Dey, AddressingMode::Implicit),
-            // EOR
-            0x49 => Self::new(Opcode::Eor, AddressingMode::Immediate),
-            0x45 => Self::new(Opcode::Eor, AddressingMode::ZeroPage),
-            0x55 => Self::new(Opcode::Eor, AddressingMode::ZeroPageX),
-            0x4D => Self::new(Opcode::Eor, AddressingMode::Absolute),
-            0x5D => Self::new(Opcode::Eor, AddressingMode::AbsoluteX),
-            0x59 => Self::new(Opcode::Eor, AddressingMode::AbsoluteY),
-            0x41 => Self::new(Opcode::Eor, AddressingMode::IndexedIndirect),
-            0x51 => Self::new(Opcode::Eor, AddressingMode::IndirectIndexed),
-            // INC
-            0xE6 => Self::new(Opcode::Inc, AddressingMode::ZeroPage),
-            0xF6 => Self::new(Opcode::Inc, AddressingMode::ZeroPageX),
-            0xEE => Self::new(Opcode::Inc, AddressingMode::Absolute),
-            0xFE => Self::new(Opcode::Inc, AddressingMode::AbsoluteX),
-            // INX
-            0xE8 => Self::new(Opcode::Inx, AddressingMode::Implicit),
-            // INY
-            0xC8 => Self::new(Opcode::Iny, AddressingMode::Implicit),
-            // JMP
-            0x4C => Self::new(Opcode::Jmp, AddressingMode::Absolute),
-            0x6C => Self::new(Opcode::Jmp, AddressingMode::Indirect),
-            // JSR
-            0x20 => Self::new(Opcode::Jsr, AddressingMode::Absolute),
-            // LDA
-            0xA9 => Self::new(Opcode::Lda, AddressingMode::Immediate),
-            0xA5 => Self::new(Opcode::Lda, AddressingMode::ZeroPage),
-            0xB5 => Self::new(Opcode::Lda, AddressingMode::ZeroPageX),
-            0xAD => Self::new(Opcode::Lda, AddressingMode::Absolute),
-            0xBD => Self::new(Opcode::Lda, AddressingMode::AbsoluteX),
-            0xB9 => Self::new(Opcode::Lda, AddressingMode::AbsoluteY),
-            0xA1 => Self::new(Opcode::Lda, AddressingMode::IndexedIndirect),
-            0xB1 => Self::new(Opcode::Lda, AddressingMode::IndirectIndexed),
-            // LDX
-            0xA2 => Self::new(Opcode::Ldx, AddressingMode::Immediate),
-            0xA6 => Self::new(Opcode::Ldx, AddressingMode::ZeroPage),
-            0xB6 => Self::new(Opcode::Ldx, AddressingMode::ZeroPageY),
-            0xAE => Self::new(Opcode::Ldx, AddressingMode::Absolute),
-            0xBE => Self::new(Opcode::Ldx, AddressingMode::AbsoluteY),
-            // LDY
-            0xA0 => Self::new(Opcode::Ldy, AddressingMode::Immediate),
-            0xA4 => Self::new(Opcode::Ldy, AddressingMode::ZeroPage),
-            0xB4 => Self::new(Opcode::Ldy, AddressingMode::ZeroPageX),
-            0xAC => Self::new(Opcode::Ldy, AddressingMode::Absolute),
-            0xBC => Self::new(Opcode::Ldy, AddressingMode::AbsoluteX),
-            // LSR
-            0x4A => Self::new(Opcode::Lsr, AddressingMode::Accumulator),
-            0x46 => Self::new(Opcode::Lsr, AddressingMode::ZeroPage),
-            0x56 => Self::new(Opcode::Lsr, AddressingMode::ZeroPageX),
-            0x4E => Self::new(Opcode::Lsr, AddressingMode::Absolute),
-            0x5E => Self::new(Opcode::Lsr, AddressingMode::AbsoluteX),
-            // NOP
-            0xEA => Self::new(Opcode::Nop, AddressingMode::Implicit),
-            // ORA
-            0x09 => Self::new(Opcode::Ora, AddressingMode::Immediate),
-            0x05 => Self::new(Opcode::Ora, AddressingMode::ZeroPage),
-            0x15 => Self::new(Opcode::Ora, AddressingMode::ZeroPageX),
-            0x0D => Self::new(Opcode::Ora, AddressingMode::Absolute),
-            0x1D => Self::new(Opcode::Ora, AddressingMode::AbsoluteX),
-            0x19 => Self::new(Opcode::Ora, AddressingMode::AbsoluteY),
-            0x01 => Self::new(Opcode::Ora, AddressingMode::IndexedIndirect),
-            0x11 => Self::new(Opcode::Ora, AddressingMode::IndirectIndexed),
-            // PHA
-            0x48 => Self::new(Opcode::Pha, AddressingMode::Implicit),
-            // PHP
-            0x08 => Self::new(Opcode::Php, AddressingMode::Implicit),
-            // PLA
-            0x68 => Self::new(Opcode::Pla, AddressingMode::Implicit),
-            // PLP
-            0x28 => Self::new(Opcode::Plp, AddressingMode::Implicit),
-            // ROL
-            0x2A => Self::new(Opcode::Rol, AddressingMode::Accumulator),
-            0x26 => Self::new(Opcode::Rol, AddressingMode::ZeroPage),
-            0x36 => Self::new(Opcode::Rol, AddressingMode::ZeroPageX),
-            0x2E => Self::new(Opcode::Rol, AddressingMode::Absolute),
-            0x3E => Self::new(Opcode::Rol, AddressingMode::AbsoluteX),
-            // ROR
-            0x6A => Self::new(Opcode::Ror, AddressingMode::Accumulator),
-            0x66 => Self::new(Opcode::Ror, AddressingMode::ZeroPage),
-            0x76 => Self::new(Opcode::Ror, AddressingMode::ZeroPageX),
-            0x6E => Self::new(Opcode::Ror, AddressingMode::Absolute),
-            0x7E => Self::new(Opcode::Ror, AddressingMode::AbsoluteX),
-            // RTI
-            0x40 => Self::new(Opcode::Rti, AddressingMode::Implicit),
-            // RTS
-            0x60 => Self::new(Opcode::Rts, AddressingMode::Implicit),
-            // SBC
-            0xE9 => Self::new(Opcode::Sbc, AddressingMode::Immediate),
-            0xE5 => Self::new(Opcode::Sbc, AddressingMode::ZeroPage),
-            0xF5 => Self::new(Opcode::Sbc, AddressingMode::ZeroPageX),
-            0xED => Self::new(Opcode::Sbc, AddressingMode::Absolute),
-            0xFD => Self::new(Opcode::Sbc, AddressingMode::AbsoluteX),
-            0xF9 => Self::new(Opcode::Sbc, AddressingMode::AbsoluteY),
-            0xE1 => Self::new(Opcode::Sbc, AddressingMode::IndexedIndirect),
-            0xF1 => Self::new(Opcode::Sbc, AddressingMode::IndirectIndexed),
-            // SEC
-            0x38 => Self::new(Opcode::Sec, AddressingMode::Implicit),
-            // SED
-            0xF8 => Self::new(Opcode::Sed, AddressingMode::Implicit),
-            // SEI
-            0x78 => Self::new(Opcode::Sei, AddressingMode::Implicit),
-            // STA
-            0x85 => Self::new(Opcode::Sta, AddressingMode::ZeroPage),
-            0x95 => Self::new(Opcode::Sta, AddressingMode::ZeroPageX),
-            0x8D => Self::new(Opcode::Sta, AddressingMode::Absolute),
-            0x9D => Self::new(Opcode::Sta, AddressingMode::AbsoluteX),
-            0x99 => Self::new(Opcode::Sta, AddressingMode::AbsoluteY),
-            0x81 => Self::new(Opcode::Sta, AddressingMode::IndexedIndirect),
-            0x91 => Self::new(Opcode::Sta, AddressingMode::IndirectIndexed),
-            // STX
-            0x86 => Self::new(Opcode::Stx, AddressingMode::ZeroPage),
-            0x96 => Self::new(Opcode::Stx, AddressingMode::ZeroPageY),
-            0x8E => Self::new(Opcode::Stx, AddressingMode::Absolute),
-            // STY
-            0x84 => Self::new(Opcode::Sty, AddressingMode::ZeroPage),
-            0x94 => Self::new(Opcode::Sty, AddressingMode::ZeroPageX),
-            0x8C => Self::new(Opcode::Sty, AddressingMode::Absolute),
-            // TAX
-            0xAA => Self::new(Opcode::Tax, AddressingMode::Implicit),
-            // TAY
-            0xA8 => Self::new(Opcode::Tay, AddressingMode::Implicit),
-            // TSX
-            0xBA => Self::new(Opcode::Tsx, AddressingMode::Implicit),
-            // TXA
-            0x8A => Self::new(Opcode::Txa, AddressingMode::Implicit),
-            // TXS
-            0x9A => Self::new(Opcode::Txs, AddressingMode::Implicit),
-            // TYA
-            0x98 => Self::new(Opcode::Tya, AddressingMode::Implicit),
-            // Unknown
-            _ => return Err(DecodeError),
-        })
+        OPCODES[value as usize]
+            .map(|info| info.instruction())
+            .ok_or(DecodeError)
+    }
+}
+
+/// Everything that is known about an opcode byte.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct OpInfo {
+    pub opcode: Opcode,
+    pub addressing_mode: AddressingMode,
+    /// The length of the instruction in bytes, including the opcode.
+    pub bytes: u8,
+    /// The number of cycles the instruction takes, without the additional cycles
+    /// for crossing a page boundary or taking a branch.
+    pub cycles: u8,
+    /// Whether the opcode isn't part of the documented instruction set.
+    pub undocumented: bool,
+}
+
+impl OpInfo {
+    pub fn instruction(&self) -> Instruction {
+        Instruction::new(self.opcode, self.addressing_mode)
     }
 }
 
+const fn documented(
+    opcode: Opcode,
+    addressing_mode: AddressingMode,
+    bytes: u8,
+    cycles: u8,
+) -> Option<OpInfo> {
+    Some(OpInfo {
+        opcode,
+        addressing_mode,
+        bytes,
+        cycles,
+        undocumented: false,
+    })
+}
+
+const fn undocumented(
+    opcode: Opcode,
+    addressing_mode: AddressingMode,
+    bytes: u8,
+    cycles: u8,
+) -> Option<OpInfo> {
+    Some(OpInfo {
+        opcode,
+        addressing_mode,
+        bytes,
+        cycles,
+        undocumented: true,
+    })
+}
+
+/// The decode table, indexed by opcode byte. Bytes that don't decode to an
+/// instruction are `None`.
+pub const OPCODES: [Option<OpInfo>; 256] = {
+    let mut table = [None; 256];
+    // ADC
+    table[0x69] = documented(Opcode::Adc, AddressingMode::Immediate, 2, 2);
+    table[0x65] = documented(Opcode::Adc, AddressingMode::ZeroPage, 2, 3);
+    table[0x75] = documented(Opcode::Adc, AddressingMode::ZeroPageX, 2, 4);
+    table[0x6D] = documented(Opcode::Adc, AddressingMode::Absolute, 3, 4);
+    table[0x7D] = documented(Opcode::Adc, AddressingMode::AbsoluteX, 3, 4);
+    table[0x79] = documented(Opcode::Adc, AddressingMode::AbsoluteY, 3, 4);
+    table[0x61] = documented(Opcode::Adc, AddressingMode::IndexedIndirect, 2, 6);
+    table[0x71] = documented(Opcode::Adc, AddressingMode::IndirectIndexed, 2, 5);
+    // AND
+    table[0x29] = documented(Opcode::And, AddressingMode::Immediate, 2, 2);
+    table[0x25] = documented(Opcode::And, AddressingMode::ZeroPage, 2, 3);
+    table[0x35] = documented(Opcode::And, AddressingMode::ZeroPageX, 2, 4);
+    table[0x2D] = documented(Opcode::And, AddressingMode::Absolute, 3, 4);
+    table[0x3D] = documented(Opcode::And, AddressingMode::AbsoluteX, 3, 4);
+    table[0x39] = documented(Opcode::And, AddressingMode::AbsoluteY, 3, 4);
+    table[0x21] = documented(Opcode::And, AddressingMode::IndexedIndirect, 2, 6);
+    table[0x31] = documented(Opcode::And, AddressingMode::IndirectIndexed, 2, 5);
+    // ASL
+    table[0x0A] = documented(Opcode::Asl, AddressingMode::Accumulator, 1, 2);
+    table[0x06] = documented(Opcode::Asl, AddressingMode::ZeroPage, 2, 5);
+    table[0x16] = documented(Opcode::Asl, AddressingMode::ZeroPageX, 2, 6);
+    table[0x0E] = documented(Opcode::Asl, AddressingMode::Absolute, 3, 6);
+    table[0x1E] = documented(Opcode::Asl, AddressingMode::AbsoluteX, 3, 7);
+    // BCC
+    table[0x90] = documented(Opcode::Bcc, AddressingMode::Relative, 2, 2);
+    // BCS
+    table[0xB0] = documented(Opcode::Bcs, AddressingMode::Relative, 2, 2);
+    // BEQ
+    table[0xF0] = documented(Opcode::Beq, AddressingMode::Relative, 2, 2);
+    // BIT
+    table[0x24] = documented(Opcode::Bit, AddressingMode::ZeroPage, 2, 3);
+    table[0x2C] = documented(Opcode::Bit, AddressingMode::Absolute, 3, 4);
+    // BMI
+    table[0x30] = documented(Opcode::Bmi, AddressingMode::Relative, 2, 2);
+    // BNE
+    table[0xD0] = documented(Opcode::Bne, AddressingMode::Relative, 2, 2);
+    // BPL
+    table[0x10] = documented(Opcode::Bpl, AddressingMode::Relative, 2, 2);
+    // BRK
+    table[0x00] = documented(Opcode::Brk, AddressingMode::Implicit, 1, 7);
+    // BVC
+    table[0x50] = documented(Opcode::Bvc, AddressingMode::Relative, 2, 2);
+    // BVS
+    table[0x70] = documented(Opcode::Bvs, AddressingMode::Relative, 2, 2);
+    // CLC
+    table[0x18] = documented(Opcode::Clc, AddressingMode::Implicit, 1, 2);
+    // CLD
+    table[0xD8] = documented(Opcode::Cld, AddressingMode::Implicit, 1, 2);
+    // CLI
+    table[0x58] = documented(Opcode::Cli, AddressingMode::Implicit, 1, 2);
+    // CLV
+    table[0xB8] = documented(Opcode::Clv, AddressingMode::Implicit, 1, 2);
+    // CMP
+    table[0xC9] = documented(Opcode::Cmp, AddressingMode::Immediate, 2, 2);
+    table[0xC5] = documented(Opcode::Cmp, AddressingMode::ZeroPage, 2, 3);
+    table[0xD5] = documented(Opcode::Cmp, AddressingMode::ZeroPageX, 2, 4);
+    table[0xCD] = documented(Opcode::Cmp, AddressingMode::Absolute, 3, 4);
+    table[0xDD] = documented(Opcode::Cmp, AddressingMode::AbsoluteX, 3, 4);
+    table[0xD9] = documented(Opcode::Cmp, AddressingMode::AbsoluteY, 3, 4);
+    table[0xC1] = documented(Opcode::Cmp, AddressingMode::IndexedIndirect, 2, 6);
+    table[0xD1] = documented(Opcode::Cmp, AddressingMode::IndirectIndexed, 2, 5);
+    // CPX
+    table[0xE0] = documented(Opcode::Cpx, AddressingMode::Immediate, 2, 2);
+    table[0xE4] = documented(Opcode::Cpx, AddressingMode::ZeroPage, 2, 3);
+    table[0xEC] = documented(Opcode::Cpx, AddressingMode::Absolute, 3, 4);
+    // CPY
+    table[0xC0] = documented(Opcode::Cpy, AddressingMode::Immediate, 2, 2);
+    table[0xC4] = documented(Opcode::Cpy, AddressingMode::ZeroPage, 2, 3);
+    table[0xCC] = documented(Opcode::Cpy, AddressingMode::Absolute, 3, 4);
+    // DEC
+    table[0xC6] = documented(Opcode::Dec, AddressingMode::ZeroPage, 2, 5);
+    table[0xD6] = documented(Opcode::Dec, AddressingMode::ZeroPageX, 2, 6);
+    table[0xCE] = documented(Opcode::Dec, AddressingMode::Absolute, 3, 6);
+    table[0xDE] = documented(Opcode::Dec, AddressingMode::AbsoluteX, 3, 7);
+    // DEX
+    table[0xCA] = documented(Opcode::Dex, AddressingMode::Implicit, 1, 2);
+    // DEY
+    table[0x88] = documented(Opcode::Dey, AddressingMode::Implicit, 1, 2);
+    // EOR
+    table[0x49] = documented(Opcode::Eor, AddressingMode::Immediate, 2, 2);
+    table[0x45] = documented(Opcode::Eor, AddressingMode::ZeroPage, 2, 3);
+    table[0x55] = documented(Opcode::Eor, AddressingMode::ZeroPageX, 2, 4);
+    table[0x4D] = documented(Opcode::Eor, AddressingMode::Absolute, 3, 4);
+    table[0x5D] = documented(Opcode::Eor, AddressingMode::AbsoluteX, 3, 4);
+    table[0x59] = documented(Opcode::Eor, AddressingMode::AbsoluteY, 3, 4);
+    table[0x41] = documented(Opcode::Eor, AddressingMode::IndexedIndirect, 2, 6);
+    table[0x51] = documented(Opcode::Eor, AddressingMode::IndirectIndexed, 2, 5);
+    // INC
+    table[0xE6] = documented(Opcode::Inc, AddressingMode::ZeroPage, 2, 5);
+    table[0xF6] = documented(Opcode::Inc, AddressingMode::ZeroPageX, 2, 6);
+    table[0xEE] = documented(Opcode::Inc, AddressingMode::Absolute, 3, 6);
+    table[0xFE] = documented(Opcode::Inc, AddressingMode::AbsoluteX, 3, 7);
+    // INX
+    table[0xE8] = documented(Opcode::Inx, AddressingMode::Implicit, 1, 2);
+    // INY
+    table[0xC8] = documented(Opcode::Iny, AddressingMode::Implicit, 1, 2);
+    // JMP
+    table[0x4C] = documented(Opcode::Jmp, AddressingMode::Absolute, 3, 3);
+    table[0x6C] = documented(Opcode::Jmp, AddressingMode::Indirect, 3, 5);
+    // JSR
+    table[0x20] = documented(Opcode::Jsr, AddressingMode::Absolute, 3, 6);
+    // LDA
+    table[0xA9] = documented(Opcode::Lda, AddressingMode::Immediate, 2, 2);
+    table[0xA5] = documented(Opcode::Lda, AddressingMode::ZeroPage, 2, 3);
+    table[0xB5] = documented(Opcode::Lda, AddressingMode::ZeroPageX, 2, 4);
+    table[0xAD] = documented(Opcode::Lda, AddressingMode::Absolute, 3, 4);
+    table[0xBD] = documented(Opcode::Lda, AddressingMode::AbsoluteX, 3, 4);
+    table[0xB9] = documented(Opcode::Lda, AddressingMode::AbsoluteY, 3, 4);
+    table[0xA1] = documented(Opcode::Lda, AddressingMode::IndexedIndirect, 2, 6);
+    table[0xB1] = documented(Opcode::Lda, AddressingMode::IndirectIndexed, 2, 5);
+    // LDX
+    table[0xA2] = documented(Opcode::Ldx, AddressingMode::Immediate, 2, 2);
+    table[0xA6] = documented(Opcode::Ldx, AddressingMode::ZeroPage, 2, 3);
+    table[0xB6] = documented(Opcode::Ldx, AddressingMode::ZeroPageY, 2, 4);
+    table[0xAE] = documented(Opcode::Ldx, AddressingMode::Absolute, 3, 4);
+    table[0xBE] = documented(Opcode::Ldx, AddressingMode::AbsoluteY, 3, 4);
+    // LDY
+    table[0xA0] = documented(Opcode::Ldy, AddressingMode::Immediate, 2, 2);
+    table[0xA4] = documented(Opcode::Ldy, AddressingMode::ZeroPage, 2, 3);
+    table[0xB4] = documented(Opcode::Ldy, AddressingMode::ZeroPageX, 2, 4);
+    table[0xAC] = documented(Opcode::Ldy, AddressingMode::Absolute, 3, 4);
+    table[0xBC] = documented(Opcode::Ldy, AddressingMode::AbsoluteX, 3, 4);
+    // LSR
+    table[0x4A] = documented(Opcode::Lsr, AddressingMode::Accumulator, 1, 2);
+    table[0x46] = documented(Opcode::Lsr, AddressingMode::ZeroPage, 2, 5);
+    table[0x56] = documented(Opcode::Lsr, AddressingMode::ZeroPageX, 2, 6);
+    table[0x4E] = documented(Opcode::Lsr, AddressingMode::Absolute, 3, 6);
+    table[0x5E] = documented(Opcode::Lsr, AddressingMode::AbsoluteX, 3, 7);
+    // NOP
+    table[0xEA] = documented(Opcode::Nop, AddressingMode::Implicit, 1, 2);
+    // NOP (undocumented)
+    table[0x1A] = undocumented(Opcode::Nop, AddressingMode::Implicit, 1, 2);
+    table[0x3A] = undocumented(Opcode::Nop, AddressingMode::Implicit, 1, 2);
+    table[0x5A] = undocumented(Opcode::Nop, AddressingMode::Implicit, 1, 2);
+    table[0x7A] = undocumented(Opcode::Nop, AddressingMode::Implicit, 1, 2);
+    table[0xDA] = undocumented(Opcode::Nop, AddressingMode::Implicit, 1, 2);
+    table[0xFA] = undocumented(Opcode::Nop, AddressingMode::Implicit, 1, 2);
+    table[0x80] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0x82] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0x89] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0xC2] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0xE2] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0x04] = undocumented(Opcode::Nop, AddressingMode::ZeroPage, 2, 3);
+    table[0x44] = undocumented(Opcode::Nop, AddressingMode::ZeroPage, 2, 3);
+    table[0x64] = undocumented(Opcode::Nop, AddressingMode::ZeroPage, 2, 3);
+    table[0x14] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0x34] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0x54] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0x74] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0xD4] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0xF4] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0x0C] = undocumented(Opcode::Nop, AddressingMode::Absolute, 3, 4);
+    table[0x1C] = undocumented(Opcode::Nop, AddressingMode::AbsoluteX, 3, 4);
+    table[0x3C] = undocumented(Opcode::Nop, AddressingMode::AbsoluteX, 3, 4);
+    table[0x5C] = undocumented(Opcode::Nop, AddressingMode::AbsoluteX, 3, 4);
+    table[0x7C] = undocumented(Opcode::Nop, AddressingMode::AbsoluteX, 3, 4);
+    table[0xDC] = undocumented(Opcode::Nop, AddressingMode::AbsoluteX, 3, 4);
+    table[0xFC] = undocumented(Opcode::Nop, AddressingMode::AbsoluteX, 3, 4);
+    // ORA
+    table[0x09] = documented(Opcode::Ora, AddressingMode::Immediate, 2, 2);
+    table[0x05] = documented(Opcode::Ora, AddressingMode::ZeroPage, 2, 3);
+    table[0x15] = documented(Opcode::Ora, AddressingMode::ZeroPageX, 2, 4);
+    table[0x0D] = documented(Opcode::Ora, AddressingMode::Absolute, 3, 4);
+    table[0x1D] = documented(Opcode::Ora, AddressingMode::AbsoluteX, 3, 4);
+    table[0x19] = documented(Opcode::Ora, AddressingMode::AbsoluteY, 3, 4);
+    table[0x01] = documented(Opcode::Ora, AddressingMode::IndexedIndirect, 2, 6);
+    table[0x11] = documented(Opcode::Ora, AddressingMode::IndirectIndexed, 2, 5);
+    // PHA
+    table[0x48] = documented(Opcode::Pha, AddressingMode::Implicit, 1, 3);
+    // PHP
+    table[0x08] = documented(Opcode::Php, AddressingMode::Implicit, 1, 3);
+    // PLA
+    table[0x68] = documented(Opcode::Pla, AddressingMode::Implicit, 1, 4);
+    // PLP
+    table[0x28] = documented(Opcode::Plp, AddressingMode::Implicit, 1, 4);
+    // ROL
+    table[0x2A] = documented(Opcode::Rol, AddressingMode::Accumulator, 1, 2);
+    table[0x26] = documented(Opcode::Rol, AddressingMode::ZeroPage, 2, 5);
+    table[0x36] = documented(Opcode::Rol, AddressingMode::ZeroPageX, 2, 6);
+    table[0x2E] = documented(Opcode::Rol, AddressingMode::Absolute, 3, 6);
+    table[0x3E] = documented(Opcode::Rol, AddressingMode::AbsoluteX, 3, 7);
+    // ROR
+    table[0x6A] = documented(Opcode::Ror, AddressingMode::Accumulator, 1, 2);
+    table[0x66] = documented(Opcode::Ror, AddressingMode::ZeroPage, 2, 5);
+    table[0x76] = documented(Opcode::Ror, AddressingMode::ZeroPageX, 2, 6);
+    table[0x6E] = documented(Opcode::Ror, AddressingMode::Absolute, 3, 6);
+    table[0x7E] = documented(Opcode::Ror, AddressingMode::AbsoluteX, 3, 7);
+    // RTI
+    table[0x40] = documented(Opcode::Rti, AddressingMode::Implicit, 1, 6);
+    // RTS
+    table[0x60] = documented(Opcode::Rts, AddressingMode::Implicit, 1, 6);
+    // SBC
+    table[0xE9] = documented(Opcode::Sbc, AddressingMode::Immediate, 2, 2);
+    table[0xE5] = documented(Opcode::Sbc, AddressingMode::ZeroPage, 2, 3);
+    table[0xF5] = documented(Opcode::Sbc, AddressingMode::ZeroPageX, 2, 4);
+    table[0xED] = documented(Opcode::Sbc, AddressingMode::Absolute, 3, 4);
+    table[0xFD] = documented(Opcode::Sbc, AddressingMode::AbsoluteX, 3, 4);
+    table[0xF9] = documented(Opcode::Sbc, AddressingMode::AbsoluteY, 3, 4);
+    table[0xE1] = documented(Opcode::Sbc, AddressingMode::IndexedIndirect, 2, 6);
+    table[0xF1] = documented(Opcode::Sbc, AddressingMode::IndirectIndexed, 2, 5);
+    // SEC
+    table[0x38] = documented(Opcode::Sec, AddressingMode::Implicit, 1, 2);
+    // SED
+    table[0xF8] = documented(Opcode::Sed, AddressingMode::Implicit, 1, 2);
+    // SEI
+    table[0x78] = documented(Opcode::Sei, AddressingMode::Implicit, 1, 2);
+    // STA
+    table[0x85] = documented(Opcode::Sta, AddressingMode::ZeroPage, 2, 3);
+    table[0x95] = documented(Opcode::Sta, AddressingMode::ZeroPageX, 2, 4);
+    table[0x8D] = documented(Opcode::Sta, AddressingMode::Absolute, 3, 4);
+    table[0x9D] = documented(Opcode::Sta, AddressingMode::AbsoluteX, 3, 5);
+    table[0x99] = documented(Opcode::Sta, AddressingMode::AbsoluteY, 3, 5);
+    table[0x81] = documented(Opcode::Sta, AddressingMode::IndexedIndirect, 2, 6);
+    table[0x91] = documented(Opcode::Sta, AddressingMode::IndirectIndexed, 2, 6);
+    // STX
+    table[0x86] = documented(Opcode::Stx, AddressingMode::ZeroPage, 2, 3);
+    table[0x96] = documented(Opcode::Stx, AddressingMode::ZeroPageY, 2, 4);
+    table[0x8E] = documented(Opcode::Stx, AddressingMode::Absolute, 3, 4);
+    // STY
+    table[0x84] = documented(Opcode::Sty, AddressingMode::ZeroPage, 2, 3);
+    table[0x94] = documented(Opcode::Sty, AddressingMode::ZeroPageX, 2, 4);
+    table[0x8C] = documented(Opcode::Sty, AddressingMode::Absolute, 3, 4);
+    // TAX
+    table[0xAA] = documented(Opcode::Tax, AddressingMode::Implicit, 1, 2);
+    // TAY
+    table[0xA8] = documented(Opcode::Tay, AddressingMode::Implicit, 1, 2);
+    // TSX
+    table[0xBA] = documented(Opcode::Tsx, AddressingMode::Implicit, 1, 2);
+    // TXA
+    table[0x8A] = documented(Opcode::Txa, AddressingMode::Implicit, 1, 2);
+    // TXS
+    table[0x9A] = documented(Opcode::Txs, AddressingMode::Implicit, 1, 2);
+    // TYA
+    table[0x98] = documented(Opcode::Tya, AddressingMode::Implicit, 1, 2);
+    table
+};
+
+/// The decode table of the 65C02. The undocumented NMOS opcodes are dropped, and
+/// the Rockwell/WDC bit instructions are added. The bytes that the 65C02 defines
+/// as NOPs decode with their fixed length and cycles, while the other instructions
+/// the 65C02 adds aren't emulated and stay `None`.
+pub const OPCODES_65C02: [Option<OpInfo>; 256] = {
+    let mut table = OPCODES;
+    let mut byte = 0;
+    while byte < table.len() {
+        if let Some(info) = table[byte] {
+            if info.undocumented {
+                table[byte] = None;
+            }
+        }
+        byte += 1;
+    }
+
+    // the columns 0x03 and 0x0B are single cycle NOPs, except for WAI and STP of
+    // the WDC 65C02 at 0xCB and 0xDB
+    let mut row = 0;
+    while row < 0x100 {
+        table[0x03 + row] = undocumented(Opcode::Nop, AddressingMode::Implicit, 1, 1);
+        if row != 0xC0 && row != 0xD0 {
+            table[0x0B + row] = undocumented(Opcode::Nop, AddressingMode::Implicit, 1, 1);
+        }
+        row += 0x10;
+    }
+    table[0x02] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0x22] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0x42] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0x62] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0x82] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0xC2] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0xE2] = undocumented(Opcode::Nop, AddressingMode::Immediate, 2, 2);
+    table[0x44] = undocumented(Opcode::Nop, AddressingMode::ZeroPage, 2, 3);
+    table[0x54] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0xD4] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0xF4] = undocumented(Opcode::Nop, AddressingMode::ZeroPageX, 2, 4);
+    table[0x5C] = undocumented(Opcode::Nop, AddressingMode::Absolute, 3, 8);
+    table[0xDC] = undocumented(Opcode::Nop, AddressingMode::Absolute, 3, 4);
+    table[0xFC] = undocumented(Opcode::Nop, AddressingMode::Absolute, 3, 4);
+
+    let mut bit = 0;
+    while bit < 8 {
+        let row = bit as usize * 0x10;
+        // RMB0-7, SMB0-7
+        table[0x07 + row] = documented(Opcode::Rmb(bit), AddressingMode::ZeroPage, 2, 5);
+        table[0x87 + row] = documented(Opcode::Smb(bit), AddressingMode::ZeroPage, 2, 5);
+        // BBR0-7, BBS0-7
+        table[0x0F + row] = documented(Opcode::Bbr(bit), AddressingMode::ZeroPageRelative, 3, 5);
+        table[0x8F + row] = documented(Opcode::Bbs(bit), AddressingMode::ZeroPageRelative, 3, 5);
+        bit += 1;
+    }
+    table
+};
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Opcode {
     Adc,
@@ -287,6 +550,215 @@ pub enum Opcode {
     Txa,
     Txs,
     Tya,
+    // 65C02 only, the number is the bit that is tested or modified
+    Rmb(u8),
+    Smb(u8),
+    Bbr(u8),
+    Bbs(u8),
+}
+
+impl Opcode {
+    /// Parses a mnemonic like `LDA` (case insensitive) into its opcode.
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        Some(match mnemonic.to_ascii_uppercase().as_str() {
+            "ADC" => Self::Adc,
+            "AND" => Self::And,
+            "ASL" => Self::Asl,
+            "BCC" => Self::Bcc,
+            "BCS" => Self::Bcs,
+            "BEQ" => Self::Beq,
+            "BIT" => Self::Bit,
+            "BMI" => Self::Bmi,
+            "BNE" => Self::Bne,
+            "BPL" => Self::Bpl,
+            "BRK" => Self::Brk,
+            "BVC" => Self::Bvc,
+            "BVS" => Self::Bvs,
+            "CLC" => Self::Clc,
+            "CLD" => Self::Cld,
+            "CLI" => Self::Cli,
+            "CLV" => Self::Clv,
+            "CMP" => Self::Cmp,
+            "CPX" => Self::Cpx,
+            "CPY" => Self::Cpy,
+            "DEC" => Self::Dec,
+            "DEX" => Self::Dex,
+            "DEY" => Self::Dey,
+            "EOR" => Self::Eor,
+            "INC" => Self::Inc,
+            "INX" => Self::Inx,
+            "INY" => Self::Iny,
+            "JMP" => Self::Jmp,
+            "JSR" => Self::Jsr,
+            "LDA" => Self::Lda,
+            "LDX" => Self::Ldx,
+            "LDY" => Self::Ldy,
+            "LSR" => Self::Lsr,
+            "NOP" => Self::Nop,
+            "ORA" => Self::Ora,
+            "PHA" => Self::Pha,
+            "PHP" => Self::Php,
+            "PLA" => Self::Pla,
+            "PLP" => Self::Plp,
+            "ROL" => Self::Rol,
+            "ROR" => Self::Ror,
+            "RTI" => Self::Rti,
+            "RTS" => Self::Rts,
+            "SBC" => Self::Sbc,
+            "SEC" => Self::Sec,
+            "SED" => Self::Sed,
+            "SEI" => Self::Sei,
+            "STA" => Self::Sta,
+            "STX" => Self::Stx,
+            "STY" => Self::Sty,
+            "TAX" => Self::Tax,
+            "TAY" => Self::Tay,
+            "TSX" => Self::Tsx,
+            "TXA" => Self::Txa,
+            "TXS" => Self::Txs,
+            "TYA" => Self::Tya,
+            upper => {
+                let (name, bit) = upper.split_at_checked(3)?;
+                let bit = bit.parse().ok().filter(|bit| *bit < 8)?;
+                match name {
+                    "RMB" => Self::Rmb(bit),
+                    "SMB" => Self::Smb(bit),
+                    "BBR" => Self::Bbr(bit),
+                    "BBS" => Self::Bbs(bit),
+                    _ => return None,
+                }
+            }
+        })
+    }
+
+    /// Every opcode, including one per bit for the 65C02 bit instructions.
+    pub fn all() -> impl Iterator<Item = Opcode> {
+        [
+            Self::Adc,
+            Self::And,
+            Self::Asl,
+            Self::Bcc,
+            Self::Bcs,
+            Self::Beq,
+            Self::Bit,
+            Self::Bmi,
+            Self::Bne,
+            Self::Bpl,
+            Self::Brk,
+            Self::Bvc,
+            Self::Bvs,
+            Self::Clc,
+            Self::Cld,
+            Self::Cli,
+            Self::Clv,
+            Self::Cmp,
+            Self::Cpx,
+            Self::Cpy,
+            Self::Dec,
+            Self::Dex,
+            Self::Dey,
+            Self::Eor,
+            Self::Inc,
+            Self::Inx,
+            Self::Iny,
+            Self::Jmp,
+            Self::Jsr,
+            Self::Lda,
+            Self::Ldx,
+            Self::Ldy,
+            Self::Lsr,
+            Self::Nop,
+            Self::Ora,
+            Self::Pha,
+            Self::Php,
+            Self::Pla,
+            Self::Plp,
+            Self::Rol,
+            Self::Ror,
+            Self::Rti,
+            Self::Rts,
+            Self::Sbc,
+            Self::Sec,
+            Self::Sed,
+            Self::Sei,
+            Self::Sta,
+            Self::Stx,
+            Self::Sty,
+            Self::Tax,
+            Self::Tay,
+            Self::Tsx,
+            Self::Txa,
+            Self::Txs,
+            Self::Tya,
+        ]
+        .into_iter()
+        .chain((0..8).flat_map(|bit| {
+            [
+                Self::Rmb(bit),
+                Self::Smb(bit),
+                Self::Bbr(bit),
+                Self::Bbs(bit),
+            ]
+        }))
+    }
+
+    /// Whether this is one of the conditional branch instructions.
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self,
+            Self::Bcc
+                | Self::Bcs
+                | Self::Beq
+                | Self::Bmi
+                | Self::Bne
+                | Self::Bpl
+                | Self::Bvc
+                | Self::Bvs
+        )
+    }
+
+    /// Whether this is JMP or JSR.
+    pub fn is_jump(&self) -> bool {
+        matches!(self, Self::Jmp | Self::Jsr)
+    }
+
+    /// Whether this is RTS or RTI.
+    pub fn is_return(&self) -> bool {
+        matches!(self, Self::Rts | Self::Rti)
+    }
+
+    /// Whether this instruction only reads its operand, and therefore takes an
+    /// additional cycle when indexing crosses a page boundary.
+    pub fn has_page_cross_penalty(&self) -> bool {
+        matches!(
+            self,
+            Self::Adc
+                | Self::And
+                | Self::Bit
+                | Self::Cmp
+                | Self::Cpx
+                | Self::Cpy
+                | Self::Eor
+                | Self::Lda
+                | Self::Ldx
+                | Self::Ldy
+                | Self::Nop
+                | Self::Ora
+                | Self::Sbc
+        )
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Rmb(bit) => write!(f, "RMB{}", bit),
+            Self::Smb(bit) => write!(f, "SMB{}", bit),
+            Self::Bbr(bit) => write!(f, "BBR{}", bit),
+            Self::Bbs(bit) => write!(f, "BBS{}", bit),
+            _ => write!(f, "{}", format!("{:?}", self).to_uppercase()),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -304,4 +776,198 @@ pub enum AddressingMode {
     Indirect,
     IndexedIndirect,
     IndirectIndexed,
+    /// A zero page address followed by a branch offset, used by BBR and BBS.
+    ZeroPageRelative,
+}
+
+impl AddressingMode {
+    /// Every addressing mode.
+    pub fn all() -> impl Iterator<Item = AddressingMode> {
+        [
+            Self::Implicit,
+            Self::Accumulator,
+            Self::Immediate,
+            Self::ZeroPage,
+            Self::ZeroPageX,
+            Self::ZeroPageY,
+            Self::Relative,
+            Self::Absolute,
+            Self::AbsoluteX,
+            Self::AbsoluteY,
+            Self::Indirect,
+            Self::IndexedIndirect,
+            Self::IndirectIndexed,
+            Self::ZeroPageRelative,
+        ]
+        .into_iter()
+    }
+
+    /// The number of operand bytes that follow the opcode.
+    pub fn operand_len(&self) -> usize {
+        match self {
+            Self::Implicit | Self::Accumulator => 0,
+            Self::Immediate
+            | Self::ZeroPage
+            | Self::ZeroPageX
+            | Self::ZeroPageY
+            | Self::Relative
+            | Self::IndexedIndirect
+            | Self::IndirectIndexed => 1,
+            Self::Absolute
+            | Self::AbsoluteX
+            | Self::AbsoluteY
+            | Self::Indirect
+            | Self::ZeroPageRelative => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn test_classify_opcode() {
+        assert_eq!(
+            classify_opcode(0xA9),
+            OpcodeClass::Legal(Instruction::new(Opcode::Lda, AddressingMode::Immediate))
+        );
+        assert_eq!(
+            classify_opcode(0x1A),
+            OpcodeClass::Illegal(Instruction::new(Opcode::Nop, AddressingMode::Implicit))
+        );
+        assert_eq!(classify_opcode(0x02), OpcodeClass::Jam);
+    }
+
+    #[test]
+    fn test_control_flow_classification() {
+        let classify = |opcode: Opcode| (opcode.is_branch(), opcode.is_jump(), opcode.is_return());
+        assert_eq!(classify(Opcode::Beq), (true, false, false));
+        assert_eq!(classify(Opcode::Jsr), (false, true, false));
+        assert_eq!(classify(Opcode::Rti), (false, false, true));
+        assert_eq!(classify(Opcode::Brk), (false, false, false));
+    }
+
+    #[test]
+    fn test_opcode_table_lengths() {
+        for (variant, table) in [("NMOS", &OPCODES), ("65C02", &OPCODES_65C02)] {
+            for (byte, info) in table.iter().enumerate() {
+                if let Some(info) = info {
+                    assert_eq!(
+                        info.bytes as usize,
+                        info.addressing_mode.operand_len() + 1,
+                        "{} opcode {:#04X}",
+                        variant,
+                        byte
+                    );
+                }
+            }
+        }
+    }
+
+    /// Checks that no two documented bytes decode to the same instruction, and that
+    /// every instruction in the table round-trips through [`Instruction::encode`].
+    fn check_opcode_table() -> Result<(), String> {
+        let mut documented = Vec::new();
+        for byte in 0..=Byte::MAX {
+            let Some(info) = OPCODES[byte as usize] else {
+                continue;
+            };
+            let instruction = info.instruction();
+            if !info.undocumented {
+                if let Some((other, _)) = documented.iter().find(|(_, i)| *i == instruction) {
+                    return Err(format!(
+                        "{:#04X} and {:#04X} both decode to {:?}",
+                        other, byte, instruction
+                    ));
+                }
+                documented.push((byte, instruction));
+            }
+
+            let encoded = instruction.encode();
+            let round_trips = match encoded {
+                Some(encoded) if !info.undocumented => encoded == byte,
+                Some(encoded) => Instruction::try_from(encoded) == Ok(instruction),
+                None => false,
+            };
+            if !round_trips {
+                return Err(format!(
+                    "{:#04X} decodes to {:?}, which encodes to {:?}",
+                    byte, instruction, encoded
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_opcode_table_consistency() {
+        assert_eq!(check_opcode_table(), Ok(()));
+    }
+
+    #[test]
+    fn test_65c02_bit_instructions() {
+        let memory = {
+            let mut memory = Memory::new();
+            memory.load(0x0400, &[0xB7, 0x10, 0x7F, 0x10, 0xFD]); // SMB3 $10; BBR7 $10,$0400
+            memory
+        };
+
+        assert_eq!(decode_at(&memory, 0x0400), None);
+        let smb = Variant::Cmos65C02.decode_at(&memory, 0x0400).unwrap();
+        assert_eq!(smb.instruction.opcode, Opcode::Smb(3));
+        assert_eq!(smb.instruction.opcode.to_string(), "SMB3");
+        let bbr = Variant::Cmos65C02.decode_at(&memory, 0x0402).unwrap();
+        assert_eq!(
+            bbr.instruction,
+            Instruction::new(Opcode::Bbr(7), AddressingMode::ZeroPageRelative)
+        );
+        assert_eq!(bbr.len, 3);
+
+        assert_eq!(Opcode::from_mnemonic("bbs5"), Some(Opcode::Bbs(5)));
+        assert_eq!(Opcode::from_mnemonic("RMB8"), None);
+        assert_eq!(OPCODES_65C02[0x1A], None, "undocumented NOPs are NMOS only");
+    }
+
+    #[test]
+    fn test_65c02_nops() {
+        let nop = |byte: usize| {
+            let info = OPCODES_65C02[byte].unwrap();
+            assert_eq!(info.opcode, Opcode::Nop, "{:#04X}", byte);
+            (info.bytes, info.cycles)
+        };
+        assert_eq!(nop(0x03), (1, 1));
+        assert_eq!(nop(0xFB), (1, 1));
+        assert_eq!(nop(0x02), (2, 2));
+        assert_eq!(nop(0x44), (2, 3));
+        assert_eq!(nop(0xF4), (2, 4));
+        assert_eq!(nop(0x5C), (3, 8));
+        assert_eq!(nop(0xDC), (3, 4));
+        assert_eq!(OPCODES_65C02[0xCB], None, "WAI");
+        assert_eq!(OPCODES_65C02[0xDB], None, "STP");
+        assert_eq!(OPCODES[0x02], None);
+    }
+
+    #[test]
+    fn test_all_opcodes_and_modes() {
+        // the 56 documented NMOS opcodes, and RMB, SMB, BBR and BBS for each bit
+        assert_eq!(Opcode::all().count(), 56 + 4 * 8);
+        assert_eq!(AddressingMode::all().count(), 14);
+
+        for opcode in Opcode::all() {
+            assert_eq!(
+                Opcode::from_mnemonic(&opcode.to_string()),
+                Some(opcode),
+                "{:?}",
+                opcode
+            );
+        }
+        for info in OPCODES.iter().chain(&OPCODES_65C02).flatten() {
+            assert!(Opcode::all().any(|opcode| opcode == info.opcode));
+            assert!(AddressingMode::all().any(|mode| mode == info.addressing_mode));
+        }
+    }
 }
@@ -0,0 +1,275 @@
+use std::sync::{Arc, Mutex};
+
+use derive_more::{Display, Error};
+use serde::Deserialize;
+
+use crate::cpu::{Byte, Cpu, CpuError, ProcessorStatus, Word};
+use crate::mem::{BusCycle, Memory};
+
+/// The CPU and RAM state before or after the instruction of a [`TestCase`].
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct TestState {
+    pub pc: Word,
+    pub s: Byte,
+    pub a: Byte,
+    pub x: Byte,
+    pub y: Byte,
+    pub p: Byte,
+    /// `[address, value]` pairs, all other addresses are zero.
+    pub ram: Vec<(Word, Byte)>,
+}
+
+/// A single test case in the format of the SingleStepTests 65x02 test vectors,
+/// which executes one instruction from the `initial` state.
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    pub initial: TestState,
+    #[serde(rename = "final")]
+    pub expected: TestState,
+    /// The bus cycles of the instruction as `[address, value, "read" | "write"]`.
+    pub cycles: Vec<(Word, Byte, String)>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TestField {
+    Pc,
+    Sp,
+    A,
+    X,
+    Y,
+    Status,
+    Ram(Word),
+    Cycles,
+}
+
+/// A field whose value after the instruction differs from the expected one.
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[display(fmt = "{:?} is {:#X}, expected {:#X}", field, actual, expected)]
+pub struct Mismatch {
+    pub field: TestField,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+/// The first bus cycle that differs from the expected one. A missing cycle is
+/// `None`.
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[display(fmt = "cycle {}: {:?}, expected {:?}", index, actual, expected)]
+pub struct BusMismatch {
+    pub index: usize,
+    pub expected: Option<BusCycle>,
+    pub actual: Option<BusCycle>,
+}
+
+#[derive(Error, Display, Debug, Clone, Eq, PartialEq)]
+pub enum TestFailure {
+    #[display(fmt = "{}", _0)]
+    Cpu(CpuError),
+    #[display(fmt = "{} fields differ, first: {}", "_0.len()", "_0[0]")]
+    Mismatches(#[error(not(source))] Vec<Mismatch>),
+    #[display(fmt = "{}", _0)]
+    Bus(#[error(not(source))] BusMismatch),
+}
+
+/// Parses a file of test cases, which is a JSON array of them.
+pub fn parse_tests(json: &str) -> serde_json::Result<Vec<TestCase>> {
+    serde_json::from_str(json)
+}
+
+impl TestCase {
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// The expected bus cycles, where every kind but `"read"` is a write.
+    pub fn expected_bus(&self) -> Vec<BusCycle> {
+        self.cycles
+            .iter()
+            .map(|(address, data, kind)| match kind.as_str() {
+                "read" => BusCycle::read(*address, *data),
+                _ => BusCycle::write(*address, *data),
+            })
+            .collect()
+    }
+
+    /// Executes the instruction on a CPU in the initial state and compares the
+    /// resulting state with the expected one.
+    ///
+    /// The break flag doesn't exist in the status register of this emulator, so it
+    /// is ignored when comparing the status. Of the bus cycles, only their number is
+    /// compared, see [`TestCase::run_with_bus`].
+    pub fn run(&self) -> Result<(), TestFailure> {
+        self.execute().map(|_| ())
+    }
+
+    /// Like [`TestCase::run`], but additionally compares every bus cycle with the
+    /// expected one.
+    ///
+    /// Dummy reads aren't emulated, so this fails for instructions that perform any
+    /// of them.
+    pub fn run_with_bus(&self) -> Result<(), TestFailure> {
+        let bus = self.execute()?;
+        let expected = self.expected_bus();
+        let index =
+            (0..expected.len().max(bus.len())).find(|&index| expected.get(index) != bus.get(index));
+        match index {
+            Some(index) => Err(TestFailure::Bus(BusMismatch {
+                index,
+                expected: expected.get(index).copied(),
+                actual: bus.get(index).copied(),
+            })),
+            None => Ok(()),
+        }
+    }
+
+    /// Executes the instruction and compares the resulting state, returning the
+    /// observed bus cycles.
+    fn execute(&self) -> Result<Vec<BusCycle>, TestFailure> {
+        let mut memory = Memory::new();
+        for &(address, value) in &self.initial.ram {
+            memory[address as usize] = value;
+        }
+        let bus = Arc::new(Mutex::new(Vec::new()));
+        let log = bus.clone();
+        memory.set_access_log(Box::new(move |kind, address, data| {
+            log.lock().unwrap().push(BusCycle {
+                address,
+                data,
+                kind,
+            })
+        }));
+
+        let mut cpu = Cpu::new(memory);
+        cpu.set_pc(self.initial.pc);
+        cpu.set_sp(self.initial.s);
+        cpu.set_a(self.initial.a);
+        cpu.set_x(self.initial.x);
+        cpu.set_y(self.initial.y);
+        cpu.set_status(ProcessorStatus::from_bits_truncate(self.initial.p));
+        let cycles = cpu.cycles;
+        cpu.step().map_err(TestFailure::Cpu)?;
+
+        let status = |p: Byte| {
+            ((p | ProcessorStatus::_Unused.bits()) & !ProcessorStatus::Break.bits()) as u64
+        };
+        let expected = &self.expected;
+        let mut fields = vec![
+            (TestField::Pc, expected.pc as u64, cpu.pc as u64),
+            (TestField::Sp, expected.s as u64, cpu.sp as u64),
+            (TestField::A, expected.a as u64, cpu.a as u64),
+            (TestField::X, expected.x as u64, cpu.x as u64),
+            (TestField::Y, expected.y as u64, cpu.y as u64),
+            (
+                TestField::Status,
+                status(expected.p),
+                status(cpu.status.bits()),
+            ),
+            (
+                TestField::Cycles,
+                self.cycles.len() as u64,
+                cpu.cycles - cycles,
+            ),
+        ];
+        fields.extend(expected.ram.iter().map(|&(address, value)| {
+            (
+                TestField::Ram(address),
+                value as u64,
+                cpu.memory.peek(address) as u64,
+            )
+        }));
+
+        let mismatches = fields
+            .into_iter()
+            .filter(|(_, expected, actual)| expected != actual)
+            .map(|(field, expected, actual)| Mismatch {
+                field,
+                expected,
+                actual,
+            })
+            .collect::<Vec<_>>();
+        if mismatches.is_empty() {
+            let bus = bus.lock().unwrap().clone();
+            Ok(bus)
+        } else {
+            Err(TestFailure::Mismatches(mismatches))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &str = r#"[
+        {
+            "name": "a9 5a 00",
+            "initial": {"pc": 8000, "s": 253, "a": 0, "x": 1, "y": 2, "p": 36, "ram": [[8000, 169], [8001, 90]]},
+            "final": {"pc": 8002, "s": 253, "a": 90, "x": 1, "y": 2, "p": 36, "ram": [[8000, 169], [8001, 90]]},
+            "cycles": [[8000, 169, "read"], [8001, 90, "read"]]
+        },
+        {
+            "name": "9d 34 12",
+            "initial": {"pc": 1024, "s": 200, "a": 119, "x": 16, "y": 0, "p": 231, "ram": [[1024, 157], [1025, 52], [1026, 18]]},
+            "final": {"pc": 1027, "s": 200, "a": 119, "x": 16, "y": 0, "p": 231, "ram": [[1024, 157], [1025, 52], [1026, 18], [4676, 119]]},
+            "cycles": [[1024, 157, "read"], [1025, 52, "read"], [1026, 18, "read"], [4676, 0, "read"], [4676, 119, "write"]]
+        }
+    ]"#;
+
+    #[test]
+    fn test_samples_pass() {
+        let tests = parse_tests(SAMPLES).unwrap();
+        assert_eq!(tests.len(), 2);
+        for test in tests {
+            assert_eq!(test.run(), Ok(()), "{}", test.name);
+        }
+    }
+
+    #[test]
+    fn test_mismatches_are_reported() {
+        let mut test = parse_tests(SAMPLES).unwrap().remove(1);
+        test.expected.a = 0x78;
+        test.expected.ram[3].1 = 0x78;
+
+        assert_eq!(
+            test.run(),
+            Err(TestFailure::Mismatches(vec![
+                Mismatch {
+                    field: TestField::A,
+                    expected: 0x78,
+                    actual: 0x77,
+                },
+                Mismatch {
+                    field: TestField::Ram(0x1244),
+                    expected: 0x78,
+                    actual: 0x77,
+                },
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_bus_cycles() {
+        let test = TestCase::from_json(
+            r#"{
+                "name": "ad 34 12",
+                "initial": {"pc": 512, "s": 255, "a": 0, "x": 0, "y": 0, "p": 38, "ram": [[512, 173], [513, 52], [514, 18], [4660, 128]]},
+                "final": {"pc": 515, "s": 255, "a": 128, "x": 0, "y": 0, "p": 164, "ram": [[512, 173], [513, 52], [514, 18], [4660, 128]]},
+                "cycles": [[512, 173, "read"], [513, 52, "read"], [514, 18, "read"], [4660, 128, "read"]]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(test.run_with_bus(), Ok(()));
+
+        let test = parse_tests(SAMPLES).unwrap().remove(1);
+        assert_eq!(
+            test.run_with_bus(),
+            Err(TestFailure::Bus(BusMismatch {
+                index: 3,
+                expected: Some(BusCycle::read(0x1244, 0)),
+                actual: Some(BusCycle::write(0x1244, 0x77)),
+            })),
+            "the dummy read of indexed stores isn't emulated"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! The save state file format, which stores a [`CpuSnapshot`].
+//!
+//! A save state starts with the magic bytes `6502` and a little-endian `u16`
+//! version, followed by the data in the layout of that version. Version 1 stores
+//! PC, SP, A, X, Y and the status, the cycle count as a little-endian `u64`, and
+//! the RAM, which takes up the rest of the file.
+
+use alloc::vec::Vec;
+
+use derive_more::Display;
+#[cfg(feature = "std")]
+use derive_more::Error;
+
+use crate::cpu::{Byte, CpuSnapshot, ProcessorStatus, Word};
+use crate::mem::MAX_MEMORY;
+
+pub const STATE_MAGIC: [Byte; 4] = *b"6502";
+/// The version that [`CpuSnapshot::to_state`] writes.
+pub const STATE_VERSION: u16 = 1;
+
+const HEADER_LEN: usize = STATE_MAGIC.len() + 2;
+const V1_REGISTERS_LEN: usize = 2 + 5 + 8;
+
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum StateError {
+    #[display(fmt = "not a save state")]
+    BadMagic,
+    #[display(
+        fmt = "unsupported save state version {}, expected at most {}",
+        version,
+        STATE_VERSION
+    )]
+    UnsupportedVersion { version: u16 },
+    #[display(fmt = "expected {} bytes, got {}", expected, actual)]
+    Truncated { expected: usize, actual: usize },
+    #[display(fmt = "invalid memory size {:#X}", size)]
+    InvalidMemorySize { size: usize },
+}
+
+impl CpuSnapshot {
+    /// Encodes the snapshot as a save state of the current [`STATE_VERSION`].
+    pub fn to_state(&self) -> Vec<Byte> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + V1_REGISTERS_LEN + self.memory.len());
+        bytes.extend(STATE_MAGIC);
+        bytes.extend(STATE_VERSION.to_le_bytes());
+        bytes.extend(self.pc.to_le_bytes());
+        bytes.extend([self.sp, self.a, self.x, self.y, self.status.bits()]);
+        bytes.extend(self.cycles.to_le_bytes());
+        bytes.extend(self.memory.iter());
+        bytes
+    }
+
+    /// Decodes a save state of any version up to [`STATE_VERSION`].
+    pub fn from_state(bytes: &[Byte]) -> Result<Self, StateError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(StateError::Truncated {
+                expected: HEADER_LEN,
+                actual: bytes.len(),
+            });
+        }
+        let (header, data) = bytes.split_at(HEADER_LEN);
+        if header[..STATE_MAGIC.len()] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        // older versions are converted here once there are any
+        match u16::from_le_bytes([header[4], header[5]]) {
+            1 => Self::from_v1(data),
+            version => Err(StateError::UnsupportedVersion { version }),
+        }
+    }
+
+    fn from_v1(data: &[Byte]) -> Result<Self, StateError> {
+        // the RAM is at least one byte long
+        let expected = V1_REGISTERS_LEN + 1;
+        if data.len() < expected {
+            return Err(StateError::Truncated {
+                expected: HEADER_LEN + expected,
+                actual: HEADER_LEN + data.len(),
+            });
+        }
+        let (registers, memory) = data.split_at(V1_REGISTERS_LEN);
+        if memory.len() > MAX_MEMORY as usize + 1 {
+            return Err(StateError::InvalidMemorySize { size: memory.len() });
+        }
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&registers[7..]);
+        Ok(Self {
+            pc: Word::from_le_bytes([registers[0], registers[1]]),
+            sp: registers[2],
+            a: registers[3],
+            x: registers[4],
+            y: registers[5],
+            status: ProcessorStatus::from_bits_retain(registers[6]),
+            cycles: u64::from_le_bytes(cycles),
+            memory: memory.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Cpu;
+    use crate::mem::Memory;
+
+    fn snapshot() -> CpuSnapshot {
+        let mut memory = Memory::new();
+        memory.load(0xC000, &[0xA9, 0x42]);
+        memory.load(0xFFFC, &[0x00, 0xC0]);
+        let mut cpu = Cpu::new(memory);
+        cpu.set_a(0x11);
+        cpu.set_y(0x22);
+        cpu.cycles = 0x1_0000_0000;
+        cpu.snapshot()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let snapshot = snapshot();
+        let state = snapshot.to_state();
+        assert_eq!(state[..6], [b'6', b'5', b'0', b'2', 0x01, 0x00]);
+        assert_eq!(CpuSnapshot::from_state(&state), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_invalid_states() {
+        let state = snapshot().to_state();
+
+        let mut bad_magic = state.clone();
+        bad_magic[0] = b'Z';
+        assert_eq!(
+            CpuSnapshot::from_state(&bad_magic),
+            Err(StateError::BadMagic)
+        );
+
+        let mut future = state.clone();
+        future[4] = 0x02;
+        assert_eq!(
+            CpuSnapshot::from_state(&future),
+            Err(StateError::UnsupportedVersion { version: 2 })
+        );
+
+        assert_eq!(
+            CpuSnapshot::from_state(&state[..10]),
+            Err(StateError::Truncated {
+                expected: 22,
+                actual: 10
+            })
+        );
+
+        let mut too_large = state.clone();
+        too_large.push(0x00);
+        assert_eq!(
+            CpuSnapshot::from_state(&too_large),
+            Err(StateError::InvalidMemorySize { size: 0x10001 })
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use core::ops::RangeInclusive;
+
+use crate::cpu::{Byte, Word};
+use crate::mem::Device;
+
+/// The offset of the low byte of the reload value and the countdown.
+pub const TIMER_LOW: Word = 0;
+/// The offset of the high byte of the reload value and the countdown.
+pub const TIMER_HIGH: Word = 1;
+/// The offset of the control register.
+pub const TIMER_CONTROL: Word = 2;
+
+/// Set in the control register while the timer is counting.
+pub const TIMER_ENABLED: Byte = 0b0000_0001;
+/// Set in the control register while the timer asserts the IRQ line.
+pub const TIMER_IRQ: Byte = 0b1000_0000;
+
+/// A countdown timer that asserts the IRQ line every time it reaches zero.
+///
+/// It occupies three registers starting at its base address:
+/// - [`TIMER_LOW`] and [`TIMER_HIGH`] read the current countdown and write the
+///   reload value. Writing the high byte restarts the countdown from the reload
+///   value and enables the timer.
+/// - [`TIMER_CONTROL`] reads [`TIMER_ENABLED`] and [`TIMER_IRQ`]. Writing it
+///   enables or disables the timer with [`TIMER_ENABLED`] and acknowledges the IRQ.
+///   Enabling a disabled timer restarts the countdown from the reload value.
+///
+/// The countdown decrements once per CPU cycle and is reloaded when it reaches zero.
+/// A reload value of zero stops the timer.
+#[derive(Debug, Clone)]
+pub struct TimerDevice {
+    base: Word,
+    reload: Word,
+    counter: Word,
+    enabled: bool,
+    irq: bool,
+}
+
+impl TimerDevice {
+    /// Creates a disabled timer whose registers start at `base`. All of them must
+    /// fit into the address space, so `base` can be at most `0xFFFD`.
+    pub fn new(base: Word) -> Self {
+        assert!(
+            base.checked_add(TIMER_CONTROL).is_some(),
+            "the timer registers don't fit at {:#06X}",
+            base
+        );
+        Self {
+            base,
+            reload: 0,
+            counter: 0,
+            enabled: false,
+            irq: false,
+        }
+    }
+
+    /// The addresses of the registers, to attach the timer with
+    /// [`Memory::attach_device`](crate::mem::Memory::attach_device).
+    pub fn range(&self) -> RangeInclusive<Word> {
+        self.base..=self.base + TIMER_CONTROL
+    }
+}
+
+impl Device for TimerDevice {
+    fn read(&self, address: Word) -> Option<Byte> {
+        let [low, high] = self.counter.to_le_bytes();
+        match address.wrapping_sub(self.base) {
+            TIMER_LOW => Some(low),
+            TIMER_HIGH => Some(high),
+            TIMER_CONTROL => Some(
+                if self.enabled { TIMER_ENABLED } else { 0 } | if self.irq { TIMER_IRQ } else { 0 },
+            ),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, address: Word, data: Byte) -> bool {
+        let [low, high] = self.reload.to_le_bytes();
+        match address.wrapping_sub(self.base) {
+            TIMER_LOW => self.reload = Word::from_le_bytes([data, high]),
+            TIMER_HIGH => {
+                self.reload = Word::from_le_bytes([low, data]);
+                self.counter = self.reload;
+                self.enabled = true;
+            }
+            TIMER_CONTROL => {
+                let enabled = data & TIMER_ENABLED != 0;
+                if enabled && !self.enabled {
+                    self.counter = self.reload;
+                }
+                self.enabled = enabled;
+                self.irq = false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if !self.enabled || self.reload == 0 {
+            return;
+        }
+        let counter = self.counter as u64;
+        if cycles < counter {
+            self.counter -= cycles as Word;
+            return;
+        }
+
+        self.irq = true;
+        let overshoot = (cycles - counter) % self.reload as u64;
+        self.counter = self.reload - overshoot as Word;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_reloads() {
+        let mut timer = TimerDevice::new(0xD000);
+        timer.write(0xD000, 10);
+        timer.write(0xD001, 0);
+
+        timer.tick(9);
+        assert_eq!(timer.read(0xD000), Some(1));
+        assert!(!timer.irq());
+
+        timer.tick(4);
+        assert!(timer.irq());
+        assert_eq!(timer.read(0xD000), Some(7), "reloaded after 10 cycles");
+        assert_eq!(timer.read(0xD002), Some(TIMER_ENABLED | TIMER_IRQ));
+
+        timer.write(0xD002, TIMER_ENABLED);
+        assert!(!timer.irq(), "acknowledged");
+        assert_eq!(timer.read(0xD003), None);
+    }
+
+    #[test]
+    fn test_timer_enable_restarts_countdown() {
+        let mut timer = TimerDevice::new(0xD000);
+        timer.write(0xD000, 10);
+        timer.tick(5);
+        assert_eq!(timer.read(0xD000), Some(0), "not counting yet");
+
+        timer.write(0xD002, TIMER_ENABLED);
+        assert_eq!(timer.read(0xD000), Some(10));
+        timer.tick(1);
+        assert!(!timer.irq());
+        assert_eq!(timer.read(0xD000), Some(9));
+
+        timer.write(0xD002, TIMER_ENABLED);
+        assert_eq!(timer.read(0xD000), Some(9), "already enabled");
+    }
+
+    #[test]
+    fn test_timer_at_end_of_memory() {
+        assert_eq!(TimerDevice::new(0xFFFD).range(), 0xFFFD..=0xFFFF);
+    }
+
+    #[test]
+    #[should_panic(expected = "don't fit at 0xFFFE")]
+    fn test_timer_past_end_of_memory() {
+        TimerDevice::new(0xFFFE);
+    }
+}
@@ -0,0 +1,109 @@
+use core::fmt::{Display, Formatter};
+
+use derive_more::Display;
+#[cfg(feature = "std")]
+use derive_more::Error;
+
+use crate::cpu::{Byte, ProcessorStatus, Word};
+
+/// The state of the CPU right before it executes the instruction at `pc`, in the
+/// same form as reference logs like the one of nestest.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TraceRecord {
+    pub pc: Word,
+    pub opcode: Byte,
+    pub a: Byte,
+    pub x: Byte,
+    pub y: Byte,
+    pub sp: Byte,
+    pub status: ProcessorStatus,
+    pub cycles: u64,
+}
+
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04X}  {:02X}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.pc,
+            self.opcode,
+            self.a,
+            self.x,
+            self.y,
+            self.status.bits(),
+            self.sp,
+            self.cycles,
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TraceField {
+    Pc,
+    Opcode,
+    A,
+    X,
+    Y,
+    Sp,
+    Status,
+    Cycles,
+}
+
+impl TraceRecord {
+    /// Returns the first field in which `other` differs from this record, with the
+    /// value of this record and the value of `other`.
+    pub fn first_difference(&self, other: &TraceRecord) -> Option<(TraceField, u64, u64)> {
+        [
+            (TraceField::Pc, self.pc as u64, other.pc as u64),
+            (TraceField::Opcode, self.opcode as u64, other.opcode as u64),
+            (TraceField::A, self.a as u64, other.a as u64),
+            (TraceField::X, self.x as u64, other.x as u64),
+            (TraceField::Y, self.y as u64, other.y as u64),
+            (TraceField::Sp, self.sp as u64, other.sp as u64),
+            (
+                TraceField::Status,
+                self.status.bits() as u64,
+                other.status.bits() as u64,
+            ),
+            (TraceField::Cycles, self.cycles, other.cycles),
+        ]
+        .into_iter()
+        .find(|(_, own, other)| own != other)
+    }
+}
+
+/// A [`TraceRecord`] together with the disassembled instruction at its PC, see
+/// [`Cpu::set_trace_callback`](crate::cpu::Cpu::set_trace_callback).
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceEntry {
+    pub record: TraceRecord,
+    pub disassembly: String,
+}
+
+/// Receives a trace entry before each instruction. It must be `Send` with the
+/// `send` feature.
+#[cfg(all(feature = "std", feature = "send"))]
+pub type TraceCallback = Box<dyn FnMut(&TraceEntry) + Send>;
+/// Receives a trace entry before each instruction. It must be `Send` with the
+/// `send` feature.
+#[cfg(all(feature = "std", not(feature = "send")))]
+pub type TraceCallback = Box<dyn FnMut(&TraceEntry)>;
+
+/// The first point at which the CPU's execution differed from a reference trace.
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Error))]
+#[display(
+    fmt = "instruction {}: {:?} is {:#X}, expected {:#X}",
+    index,
+    field,
+    actual,
+    expected
+)]
+pub struct Divergence {
+    /// The index of the instruction in the reference trace.
+    pub index: usize,
+    pub field: TraceField,
+    pub expected: u64,
+    pub actual: u64,
+}
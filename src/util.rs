@@ -0,0 +1,46 @@
+use derive_more::Display;
+#[cfg(feature = "std")]
+use derive_more::Error;
+
+use crate::cpu::Word;
+
+/// A branch target that a single branch can't reach, see [`relative_offset`].
+#[derive(Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Error))]
+#[display(
+    fmt = "branch target is {} bytes away, which is out of range",
+    distance
+)]
+pub struct OutOfRange {
+    pub distance: i16,
+}
+
+/// Computes the offset that a branch needs to reach `target`, where `from_pc_after`
+/// is the address of the instruction following the branch, which offsets are
+/// relative to. Like the PC, the distance wraps around the end of memory.
+pub fn relative_offset(from_pc_after: Word, target: Word) -> Result<i8, OutOfRange> {
+    let distance = target.wrapping_sub(from_pc_after) as i16;
+    i8::try_from(distance).map_err(|_| OutOfRange { distance })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_offset() {
+        assert_eq!(relative_offset(0xC002, 0xC010), Ok(0x0E));
+        assert_eq!(relative_offset(0xC002, 0xC081), Ok(127));
+        assert_eq!(relative_offset(0xC082, 0xC002), Ok(-128));
+        assert_eq!(relative_offset(0xFFF0, 0x0010), Ok(0x20), "wraps");
+
+        assert_eq!(
+            relative_offset(0xC083, 0xC002),
+            Err(OutOfRange { distance: -129 })
+        );
+        assert_eq!(
+            relative_offset(0xC002, 0xC100),
+            Err(OutOfRange { distance: 254 })
+        );
+    }
+}
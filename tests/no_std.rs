@@ -0,0 +1,30 @@
+use std::process::Command;
+
+/// Builds the library without the `std` feature, which is what a `no_std`
+/// user of this crate gets, together with all other targets, so that tests and
+/// examples that need `std` are gated accordingly.
+#[test]
+fn builds_without_std() {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let output = Command::new(env!("CARGO"))
+        .args([
+            "build",
+            "--all-targets",
+            "--offline",
+            "--no-default-features",
+        ])
+        .current_dir(manifest_dir)
+        // a separate target directory avoids waiting on the lock held by `cargo test`
+        .env(
+            "CARGO_TARGET_DIR",
+            format!("{}/target/no_std", manifest_dir),
+        )
+        .output()
+        .expect("failed to run cargo");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}